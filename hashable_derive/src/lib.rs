@@ -0,0 +1,90 @@
+//! `#[derive(Hashable)]` for the `Hashable` trait of `fifa_sorter`.
+//!
+//! The generated impl feeds every field into the hasher in declaration
+//! order. Enums write the index of the variant first, so `A(1)` and `B(1)`
+//! hash differently. The impl names the trait through
+//! `crate::structures::hash_table::Hashable`, so the derive is meant to be
+//! used inside `fifa_sorter`.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Ident, Span};
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Fields, Index};
+
+#[proc_macro_derive(Hashable)]
+pub fn derive_hashable(input: TokenStream) -> TokenStream {
+    let mut input = parse_macro_input!(input as DeriveInput);
+    let hashable = quote!(crate::structures::hash_table::Hashable);
+
+    for param in input.generics.type_params_mut() {
+        param.bounds.push(parse_quote!(#hashable));
+    }
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let body = match &input.data {
+        Data::Struct(data) => {
+            let fields = data.fields.iter().enumerate().map(|(i, field)| {
+                let member = match &field.ident {
+                    Some(ident) => quote!(#ident),
+                    None => {
+                        let index = Index::from(i);
+                        quote!(#index)
+                    }
+                };
+                quote!(#hashable::hash(&self.#member, state);)
+            });
+            quote!(#(#fields)*)
+        }
+        Data::Enum(data) => {
+            let arms = data.variants.iter().enumerate().map(|(i, variant)| {
+                let ident = &variant.ident;
+                let bindings = binding_names(&variant.fields);
+                let pattern = match &variant.fields {
+                    Fields::Named(fields) => {
+                        let names = fields.named.iter().map(|f| &f.ident);
+                        quote!({ #(#names: #bindings),* })
+                    }
+                    Fields::Unnamed(_) => quote!(( #(#bindings),* )),
+                    Fields::Unit => quote!(),
+                };
+                quote! {
+                    Self::#ident #pattern => {
+                        state.write_usize(#i);
+                        #(#hashable::hash(#bindings, state);)*
+                    }
+                }
+            });
+            quote! {
+                match self {
+                    #(#arms)*
+                }
+            }
+        }
+        Data::Union(data) => {
+            return syn::Error::new_spanned(
+                data.union_token,
+                "Hashable can't be derived for unions",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    quote! {
+        impl #impl_generics #hashable for #name #ty_generics #where_clause {
+            // Unit structs and variants have nothing to feed `state`.
+            #[allow(unused_variables)]
+            fn hash<H: ::std::hash::Hasher>(&self, state: &mut H) {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+fn binding_names(fields: &Fields) -> Vec<Ident> {
+    (0..fields.len())
+        .map(|i| format_ident!("__field{}", i, span = Span::call_site()))
+        .collect()
+}
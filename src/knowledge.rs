@@ -1,141 +1,478 @@
-use anyhow::anyhow;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
 
 use crate::structures::btree::BTree;
+use crate::structures::ord_float::OrdF32;
+use crate::structures::paged_btree::PagedBTree;
 use crate::{
     models::{Jogador, JogadorComRating, Rating, Tag, User},
     parser::Query,
-    structures::{hash_table::HashTable, multi_tst::MultiTst},
+    structures::{
+        hash_table::{HashTable, TableStats},
+        hasher::SipHash,
+        map::Map,
+        multi_tst::MultiTst,
+        sharded_table::ShardedTable,
+        tst::normalize,
+    },
 };
 
-const JOGADOR_SIZE: usize = 22_807;
-const TAG_SIZE: usize = 438_001;
-const USER_SIZE: usize = 200_001;
+#[cfg(feature = "open_table")]
+use crate::structures::open_table::OpenTable;
+
+// Map behind the player and user tables. Build with `--features open_table`
+// to compare the open-addressing table against the chained one.
+#[cfg(not(feature = "open_table"))]
+type Table<K, V> = HashTable<K, V>;
+#[cfg(feature = "open_table")]
+type Table<K, V> = OpenTable<K, V>;
+
+// Players need at least this many ratings to show up in the position indexes.
+const MIN_POS_RATINGS: u32 = 1000;
+
+// Position indexes are saved here by `DB::finish_init`, one page file per
+// position, and read back on the next start if the ratings didn't change.
+// Checking them still takes every rating, so this only saves sorting the
+// entries. The files are a cache: losing them just means a rebuild.
+const POS_DIR: &str = "data/posicoes";
+
+// Fuzzy name searches allow one typo every `LETTERS_PER_TYPO` letters, up to
+// `MAX_TYPOS`.
+const LETTERS_PER_TYPO: usize = 5;
+const MAX_TYPOS: usize = 3;
+
+// Players listed by `suggest` and offered as completions.
+const SUGGESTIONS: usize = 10;
+
+// Position indexes are keyed by (rating, rating count, sofifa id), so players
+// with the same average always come out in the same order.
+type PosKey = (OrdF32, u32, u32);
+
+fn pos_key(jogador: &JogadorComRating) -> PosKey {
+    (
+        OrdF32(jogador.get_rating()),
+        jogador.get_rating_count(),
+        jogador.get_sofifa_id(),
+    )
+}
+
+// Words of a player name as indexed for prefix search. Hyphenated names
+// count as separate words, so "min" finds "Son Heung-min".
+fn tokens(name: &str) -> impl Iterator<Item = &str> {
+    name.split(|c: char| c.is_whitespace() || c == '-')
+        .filter(|token| !token.is_empty())
+}
+
+// Number of players asked for by a top or bottom query.
+fn count(n: i32) -> Result<usize, anyhow::Error> {
+    usize::try_from(n).map_err(|_| anyhow!("Quantidade de jogadores não pode ser negativa"))
+}
+
+// A NaN average would sort before or after every other player depending on
+// its sign bit, so only finite ones are indexed.
+fn in_pos_ht(jogador: &JogadorComRating) -> bool {
+    jogador.get_rating_count() > MIN_POS_RATINGS && jogador.get_rating().is_finite()
+}
+
+// Positions come from the players file, so they're checked before being
+// used as file names.
+fn pos_path(dir: &Path, pos: &str) -> Result<PathBuf, anyhow::Error> {
+    if pos.is_empty() || !pos.chars().all(char::is_alphanumeric) {
+        bail!("Posição inválida: {pos:?}");
+    }
+    Ok(dir.join(format!("{pos}.db")))
+}
 
-struct JogadoresDB {
-    ht: HashTable<u32, JogadorComRating>,
+struct JogadoresDB<M = Table<u32, JogadorComRating>> {
+    ht: M,
     full_trie: MultiTst<u32>,
-    tag: HashTable<String, Vec<u32>>,
-    pos_ht: HashTable<String, BTree<f32, u32>>,
+    // Every word of every name, so a search can start at any of them.
+    token_trie: MultiTst<u32>,
+    // Tags are free text typed by users, so they get a seeded hash.
+    tag: HashTable<String, Vec<u32>, SipHash>,
+    pos_ht: HashTable<String, BTree<PosKey, u32>>,
+    pos_populated: bool,
 }
 
-impl JogadoresDB {
+impl<M: Map<u32, JogadorComRating>> JogadoresDB<M> {
     fn new() -> Self {
-        let ht = HashTable::new(JOGADOR_SIZE);
+        let ht = M::default();
         let full_trie = MultiTst::new();
-        let tag = HashTable::new(TAG_SIZE);
-        let pos_ht = HashTable::new(101);
+        let token_trie = MultiTst::new();
+        let tag = HashTable::default();
+        let pos_ht = HashTable::default();
 
         JogadoresDB {
             ht,
             full_trie,
+            token_trie,
             tag,
             pos_ht,
+            pos_populated: false,
         }
     }
 
     fn insert(&mut self, jogador: &Jogador) -> Result<(), anyhow::Error> {
         // println!("Inserting jogador {} - {}", jogador.get_id(), jogador.get_name());
         self.ht
-            .insert(&jogador.get_id(), JogadorComRating::from(jogador.clone()))?;
+            .insert(jogador.get_id(), JogadorComRating::from(jogador.clone()))?;
         self.full_trie
             .insert(jogador.get_name().clone(), jogador.get_id())?;
+        for token in tokens(jogador.get_name()) {
+            self.token_trie
+                .insert(token.to_string(), jogador.get_id())?;
+        }
 
         Ok(())
     }
 
-    fn get(&self, id: u32) -> Option<JogadorComRating> {
+    fn get(&self, id: u32) -> Option<&JogadorComRating> {
         self.ht.get(&id)
     }
 
-    fn search(&self, name: String) -> Vec<JogadorComRating> {
-        self.full_trie
-            .find(name)
-            .iter()
-            .map(|a| self.get(*a).unwrap())
-            .collect()
+    // Players whose full name starts with `name`, followed by the ones that
+    // have a word starting with each word of `name`, so "messi" finds
+    // "Lionel Andrés Messi Cuccittini".
+    fn search(&self, name: String) -> Result<Vec<&JogadorComRating>, anyhow::Error> {
+        let words = tokens(&name).map(normalize).collect::<Vec<String>>();
+        let mut seen: HashTable<u32, ()> = HashTable::default();
+        let mut jogadores = vec![];
+        for id in self.full_trie.find(name) {
+            seen.insert(&id, ())?;
+            jogadores.push(self.get(id).unwrap());
+        }
+
+        let Some((first, rest)) = words.split_first() else {
+            return Ok(jogadores);
+        };
+        for id in self.token_trie.find(first.clone()) {
+            if seen.contains_key(&id) {
+                continue;
+            }
+            let jogador = self.get(id).unwrap();
+            let name = normalize(jogador.get_name());
+            if rest
+                .iter()
+                .all(|word| tokens(&name).any(|token| token.starts_with(word.as_str())))
+            {
+                seen.insert(&id, ())?;
+                jogadores.push(jogador);
+            }
+        }
+        Ok(jogadores)
+    }
+
+    // Players named within a few typos of `name`, also trying its words in
+    // other orders so "Ronaldo Cristiano" finds "Cristiano Ronaldo". Closest
+    // names come first, ties broken by the number of ratings.
+    fn search_similar(&self, name: &str) -> Vec<(usize, &JogadorComRating)> {
+        let max = (name.chars().count() / LETTERS_PER_TYPO).clamp(1, MAX_TYPOS);
+        let words = name.split_whitespace().collect::<Vec<&str>>();
+        let mut found = (0..words.len())
+            .flat_map(|i| {
+                let rotated = [&words[i..], &words[..i]].concat().join(" ");
+                self.full_trie.find_similar(rotated, max)
+            })
+            .collect::<Vec<(u32, usize)>>();
+        // Keep the closest match of each player.
+        found.sort_unstable();
+        found.dedup_by_key(|(id, _)| *id);
+
+        let mut jogadores = found
+            .into_iter()
+            .map(|(id, distance)| (distance, self.get(id).unwrap()))
+            .collect::<Vec<(usize, &JogadorComRating)>>();
+        jogadores.sort_by_key(|(distance, jogador)| {
+            (*distance, std::cmp::Reverse(jogador.get_rating_count()))
+        });
+        jogadores
     }
 
     fn insert_tag(&mut self, tag: &Tag) -> Result<(), anyhow::Error> {
-        if let Some(jogadores) = self.tag.get_mut(&tag.get_tag().to_lowercase()) {
-            jogadores.push(tag.get_id());
-        } else {
-            self.tag
-                .insert(&tag.get_tag().to_lowercase(), vec![tag.get_id()])?;
-        }
+        self.tag
+            .entry(tag.get_tag().to_lowercase())
+            .or_try_insert_with(Vec::new)?
+            .push(tag.get_id());
         self.ht.get_mut(&tag.get_id()).unwrap().add_tag(tag);
         Ok(())
     }
 
     fn add_rating(&mut self, rating: &Rating) -> Result<(), anyhow::Error> {
-        self.ht
-            .get_mut(&rating.get_sofifa_id())
-            .ok_or_else(|| anyhow!("Jogador não encontrado"))?
-            .add_rating(rating.get_rating());
-        Ok(())
+        self.update_rating(rating.get_sofifa_id(), |jogador| {
+            jogador.add_rating(rating.get_rating());
+        })
     }
 
-    fn populate_pos_ht(&mut self) {
-        self.ht.for_each(|_, jogador| {
+    fn add_rating_sum(&mut self, id: u32, sum: RatingSum) -> Result<(), anyhow::Error> {
+        self.update_rating(id, |jogador| jogador.add_ratings(sum.sum, sum.count))
+    }
+
+    // Changes the rating of a player, keeping the position indexes in sync.
+    fn update_rating<F>(&mut self, id: u32, f: F) -> Result<(), anyhow::Error>
+    where
+        F: FnOnce(&mut JogadorComRating),
+    {
+        let jogador = self
+            .ht
+            .get_mut(&id)
+            .ok_or_else(|| anyhow!("Jogador não encontrado"))?;
+        let old_key = pos_key(jogador);
+        let was_indexed = in_pos_ht(jogador);
+        f(jogador);
+
+        if self.pos_populated {
             let positions = jogador.get_pos().player_positions.clone();
-            let rating = jogador.get_rating();
-            let id = jogador.get_sofifa_id();
-            let count = jogador.get_rating_count();
+            let new_key = pos_key(jogador);
+            let indexed = in_pos_ht(jogador);
             for pos in positions {
-                if self.pos_ht.contains_key(&pos) {
-                    if count > 1000 {
-                        self.pos_ht.get_mut(&pos).unwrap().insert(rating, id);
+                let btree = self.pos_ht.get_mut_or_default(&pos)?;
+                if was_indexed {
+                    btree.remove(&old_key, &id);
+                }
+                if indexed {
+                    btree.insert(new_key, id);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn populate_pos_ht(&mut self) {
+        let mut by_pos: HashTable<String, Vec<(PosKey, u32)>> = HashTable::default();
+        for (_, jogador) in self.ht.iter() {
+            for pos in &jogador.get_pos().player_positions {
+                let entries = by_pos.entry(pos.clone()).or_default();
+                if in_pos_ht(jogador) {
+                    entries.push((pos_key(jogador), jogador.get_sofifa_id()));
+                }
+            }
+        }
+        self.pos_ht = by_pos
+            .into_iter()
+            .map(|(pos, mut entries)| {
+                entries.sort_unstable_by_key(|a| a.0);
+                (pos, BTree::from_sorted_iter(entries))
+            })
+            .collect();
+        self.pos_populated = true;
+    }
+
+    // Writes the position indexes to `dir`. Files left by an earlier run are
+    // updated in place, so only the entries that changed get written.
+    fn save_pos_ht(&self, dir: &Path) -> Result<(), anyhow::Error> {
+        fs::create_dir_all(dir)?;
+        for (pos, btree) in &self.pos_ht {
+            let path = pos_path(dir, pos)?;
+            let mut file = match PagedBTree::open(&path) {
+                Ok(file) => file,
+                Err(_) => PagedBTree::create(&path)?,
+            };
+            let mut saved = file.get_all()?.into_iter().peekable();
+            let mut current = btree.iter().map(|(k, v)| (*k, *v)).peekable();
+            // Both are sorted and every key holds a different player.
+            loop {
+                match (saved.peek(), current.peek()) {
+                    (Some(old), Some(new)) if old == new => {
+                        saved.next();
+                        current.next();
                     }
-                } else {
-                    let mut btree = BTree::new();
-                    if count > 1000 {
-                        btree.insert(rating, id);
+                    (Some(old), new) if new.is_none_or(|new| old.0 < new.0) => {
+                        file.remove(&old.0, &old.1)?;
+                        saved.next();
+                    }
+                    (_, Some(&(key, id))) => {
+                        file.insert(key, id)?;
+                        current.next();
                     }
-                    self.pos_ht.insert(&pos, btree).unwrap();
+                    (_, None) => break,
                 }
             }
-        });
+            file.close()?;
+        }
+        Ok(())
+    }
+
+    // Reads the position indexes written by `save_pos_ht`, failing if one is
+    // missing or doesn't match the players' current ratings.
+    fn load_pos_ht(&mut self, dir: &Path) -> Result<(), anyhow::Error> {
+        let mut expected: HashTable<String, usize> = HashTable::default();
+        for (_, jogador) in self.ht.iter() {
+            for pos in &jogador.get_pos().player_positions {
+                let count = expected.entry(pos.clone()).or_default();
+                if in_pos_ht(jogador) {
+                    *count += 1;
+                }
+            }
+        }
+
+        let mut pos_ht = HashTable::default();
+        for (pos, count) in &expected {
+            let mut file = PagedBTree::<PosKey, u32>::open(pos_path(dir, pos)?)?;
+            let entries = file.get_all()?;
+            file.close()?;
+            let up_to_date = entries.len() == *count
+                && entries.windows(2).all(|w| w[0].0 < w[1].0)
+                && entries.iter().all(|(key, id)| {
+                    self.get(*id).is_some_and(|jogador| {
+                        in_pos_ht(jogador)
+                            && pos_key(jogador) == *key
+                            && jogador.get_pos().player_positions.contains(pos)
+                    })
+                });
+            if !up_to_date {
+                bail!("Índice da posição {pos} desatualizado");
+            }
+            pos_ht.insert(pos, BTree::from_sorted_iter(entries))?;
+        }
+        self.pos_ht = pos_ht;
+        self.pos_populated = true;
+        Ok(())
+    }
+
+    // Ranks name completions by popularity: a key weighs as much as the
+    // player under it with the most ratings.
+    fn weight_tries(&mut self) {
+        for (_, jogador) in self.ht.iter() {
+            let count = u64::from(jogador.get_rating_count());
+            let name = jogador.get_name();
+            self.full_trie.raise_weight(name.clone(), count);
+            for token in tokens(name) {
+                self.token_trie.raise_weight(token.to_string(), count);
+            }
+        }
+    }
+
+    // The `k` players with most ratings with a name, or a word of it,
+    // starting with `prefix`.
+    fn suggest(&self, prefix: &str, k: usize) -> Vec<&JogadorComRating> {
+        let mut jogadores = [&self.full_trie, &self.token_trie]
+            .into_iter()
+            .flat_map(|trie| self.top_in(trie, prefix, k))
+            .collect::<Vec<&JogadorComRating>>();
+        jogadores.sort_unstable_by_key(|a| a.get_sofifa_id());
+        jogadores.dedup_by_key(|a| a.get_sofifa_id());
+        jogadores.sort_by_key(|a| (std::cmp::Reverse(a.get_rating_count()), a.get_sofifa_id()));
+        jogadores.truncate(k);
+        jogadores
+    }
+
+    // Like `suggest` for a single trie. A player can be under several of
+    // the keys fetched, so keys are fetched until `k` players are known to
+    // have more ratings than any key left. Keys left as heavy as the last
+    // one fetched could hold a tied player with a smaller id.
+    fn top_in(&self, trie: &MultiTst<u32>, prefix: &str, k: usize) -> Vec<&JogadorComRating> {
+        let mut keys = k;
+        loop {
+            let found = trie.find_top(prefix.to_string(), keys);
+            let exhausted = found.len() < keys;
+            let lightest = found.last().map_or(0, |(_, weight)| *weight);
+            let mut ids = found
+                .into_iter()
+                .flat_map(|(ids, _)| ids)
+                .collect::<Vec<u32>>();
+            ids.sort_unstable();
+            ids.dedup();
+            let jogadores = ids
+                .into_iter()
+                .map(|id| self.get(id).unwrap())
+                .collect::<Vec<&JogadorComRating>>();
+            let ahead = jogadores
+                .iter()
+                .filter(|a| u64::from(a.get_rating_count()) > lightest)
+                .count();
+            if exhausted || ahead >= k {
+                return jogadores;
+            }
+            keys *= 2;
+        }
     }
 }
 
-struct UsersDB {
-    ht: HashTable<u32, User>,
+struct UsersDB<M = Table<u32, User>> {
+    ht: M,
 }
 
-impl UsersDB {
+impl<M: Map<u32, User>> UsersDB<M> {
     fn new() -> Self {
-        let ht = HashTable::new(USER_SIZE);
+        let ht = M::default();
 
         UsersDB { ht }
     }
 
-    fn insert(&mut self, user: User) -> Result<(), anyhow::Error> {
-        self.ht.insert(&user.get_id(), user)?;
-
-        Ok(())
+    fn get(&self, id: u32) -> Option<&User> {
+        self.ht.get(&id)
     }
 
-    fn get(&self, id: u32) -> Option<User> {
-        self.ht.get(&id)
+    fn get_or_insert(&mut self, id: u32) -> Result<&mut User, anyhow::Error> {
+        self.ht.get_or_try_insert_with(id, || User::new(id))
     }
+}
 
-    fn get_mut(&mut self, id: u32) -> Option<&mut User> {
-        self.ht.get_mut(&id)
+// Sum and count of the ratings a player got during parallel ingestion.
+#[derive(Debug, Clone, Copy, Default)]
+struct RatingSum {
+    sum: f64,
+    count: u32,
+}
+
+/// Ratings added from several threads at once, merged into the database
+/// afterwards with [`DB::merge_ratings`].
+#[derive(Default)]
+pub struct RatingIngest {
+    // Ratings of each user along with the line they were read from, as
+    // threads add them in no particular order.
+    users: ShardedTable<u32, Vec<(usize, Rating)>>,
+    jogadores: ShardedTable<u32, RatingSum>,
+}
+
+impl RatingIngest {
+    /// Adds `rating`, read from the given `line` of the ratings file.
+    pub fn add(&self, line: usize, rating: &Rating) -> Result<(), anyhow::Error> {
+        self.users
+            .update_or_insert_with(rating.get_user_id(), Vec::new, |ratings| {
+                ratings.push((line, rating.clone()));
+            })?;
+        self.jogadores.update_or_insert_with(
+            rating.get_sofifa_id(),
+            RatingSum::default,
+            |sum| {
+                sum.sum += f64::from(rating.get_rating());
+                sum.count += 1;
+            },
+        )?;
+        Ok(())
     }
 }
 
-pub struct DB {
-    jogadores: JogadoresDB,
-    users: UsersDB,
+pub struct DB<J = Table<u32, JogadorComRating>, U = Table<u32, User>> {
+    jogadores: JogadoresDB<J>,
+    users: UsersDB<U>,
 }
 
+// Results borrow from the `DB` that ran the query, so listing players never
+// copies them.
 #[derive(Debug)]
-pub enum QueryResult {
-    Jogador(JogadorComRating),
-    Jogadores(Vec<JogadorComRating>),
-    User(User),
+pub enum QueryResult<'a> {
+    Jogador(&'a JogadorComRating),
+    Jogadores(Vec<&'a JogadorComRating>),
+    User(&'a User),
+    Rank {
+        position: String,
+        total: usize,
+        jogadores: Vec<(usize, &'a JogadorComRating)>,
+    },
+    // Players with their edit distance to the name searched.
+    Similar(Vec<(usize, &'a JogadorComRating)>),
+    Stats(Vec<(&'static str, TableStats)>),
 }
 
-impl DB {
+impl<J, U> DB<J, U>
+where
+    J: Map<u32, JogadorComRating>,
+    U: Map<u32, User>,
+{
     pub fn new() -> Self {
         let jogadores = JogadoresDB::new();
         let users = UsersDB::new();
@@ -150,48 +487,110 @@ impl DB {
     }
 
     pub fn insert_rating(&mut self, rating: &Rating) -> Result<(), anyhow::Error> {
-        if let Some(user) = self.users.get_mut(rating.get_user_id()) {
-            user.add_rating(rating);
-        } else {
-            self.users.insert(User::from_rating(rating.clone()))?;
-        }
+        self.users
+            .get_or_insert(rating.get_user_id())?
+            .add_rating(rating);
         self.jogadores.add_rating(rating)?;
 
         Ok(())
     }
 
-    pub fn get_jogador(&self, id: u32) -> Option<JogadorComRating> {
+    /// Adds the ratings of `ingest`. Each user gets theirs in file order,
+    /// the same as if they were read one by one.
+    pub fn merge_ratings(&mut self, ingest: RatingIngest) -> Result<(), anyhow::Error> {
+        for (id, mut ratings) in ingest.users {
+            ratings.sort_unstable_by_key(|(line, _)| *line);
+            let user = self.users.get_or_insert(id)?;
+            for (_, rating) in &ratings {
+                user.add_rating(rating);
+            }
+        }
+        for (id, sum) in ingest.jogadores {
+            self.jogadores.add_rating_sum(id, sum)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn get_jogador(&self, id: u32) -> Option<&JogadorComRating> {
         self.jogadores.get(id)
     }
 
-    pub fn get_user(&self, id: u32) -> Option<User> {
+    pub fn get_user(&self, id: u32) -> Option<&User> {
         self.users.get(id)
     }
 
-    pub fn search_jogador(&self, name: String) -> Vec<JogadorComRating> {
+    pub fn search_jogador(&self, name: String) -> Result<Vec<&JogadorComRating>, anyhow::Error> {
         self.jogadores.search(name)
     }
 
+    pub fn search_similar(&self, name: &str) -> Vec<(usize, &JogadorComRating)> {
+        self.jogadores.search_similar(name)
+    }
+
+    pub fn search_glob(&self, pattern: String) -> Result<Vec<&JogadorComRating>, anyhow::Error> {
+        Ok(self
+            .jogadores
+            .full_trie
+            .find_glob(pattern)?
+            .into_iter()
+            .map(|id| self.jogadores.get(id).unwrap())
+            .collect())
+    }
+
     pub fn insert_tag(&mut self, tag: &Tag) -> Result<(), anyhow::Error> {
         self.jogadores.insert_tag(tag)?;
 
         Ok(())
     }
 
+    /// Bucket statistics of every hash table in the database.
+    pub fn stats(&self) -> Vec<(&'static str, TableStats)> {
+        vec![
+            ("jogadores", self.jogadores.ht.stats()),
+            ("tags", self.jogadores.tag.stats()),
+            ("usuarios", self.users.ht.stats()),
+            ("posicoes", self.jogadores.pos_ht.stats()),
+        ]
+    }
+
+    /// Builds the position indexes, reading them back from the last run if
+    /// the ratings didn't change, and ranks name completions. The saved
+    /// indexes are only a cache, so failing to write them is reported on
+    /// stderr and startup goes on.
     pub fn finish_init(&mut self) {
-        self.jogadores.populate_pos_ht();
+        self.finish_init_in(Path::new(POS_DIR));
     }
 
-    pub fn run_query(&self, query: Query) -> Result<QueryResult, anyhow::Error> {
+    fn finish_init_in(&mut self, dir: &Path) {
+        if self.jogadores.load_pos_ht(dir).is_err() {
+            self.jogadores.populate_pos_ht();
+            if let Err(err) = self.jogadores.save_pos_ht(dir) {
+                eprintln!("Não foi possível salvar os índices de posição: {err}");
+            }
+        }
+        self.jogadores.weight_tries();
+    }
+
+    /// Players with most ratings whose name, or a word of it, starts with
+    /// `prefix`.
+    pub fn suggest(&self, prefix: &str) -> Vec<&JogadorComRating> {
+        self.jogadores.suggest(prefix, SUGGESTIONS)
+    }
+
+    pub fn run_query(&self, query: Query) -> Result<QueryResult<'_>, anyhow::Error> {
         match query {
             Query::Player(name) => {
-                let jogadores = self.search_jogador(name);
+                let jogadores = self.search_jogador(name)?;
                 if jogadores.len() == 1 {
-                    Ok(QueryResult::Jogador(jogadores[0].clone()))
+                    Ok(QueryResult::Jogador(jogadores[0]))
                 } else {
                     Ok(QueryResult::Jogadores(jogadores))
                 }
             }
+            Query::Similar(name) => Ok(QueryResult::Similar(self.search_similar(&name))),
+            Query::Glob(pattern) => Ok(QueryResult::Jogadores(self.search_glob(pattern)?)),
+            Query::Suggest(prefix) => Ok(QueryResult::Jogadores(self.suggest(&prefix))),
             Query::User(id) => {
                 if let Some(user) = self.get_user(id) {
                     Ok(QueryResult::User(user))
@@ -205,10 +604,23 @@ impl DB {
                     .pos_ht
                     .get(&position)
                     .ok_or_else(|| anyhow!("Position not found"))?
-                    .get_greatest_n(n as u32)
                     .iter()
-                    .map(|a| self.jogadores.get(*a).unwrap())
-                    .collect::<Vec<JogadorComRating>>();
+                    .rev()
+                    .take(count(n)?)
+                    .map(|(_, a)| self.jogadores.get(*a).unwrap())
+                    .collect::<Vec<&JogadorComRating>>();
+                Ok(QueryResult::Jogadores(jogadores))
+            }
+            Query::Bottom(n, position) => {
+                let jogadores = self
+                    .jogadores
+                    .pos_ht
+                    .get(&position)
+                    .ok_or_else(|| anyhow!("Position not found"))?
+                    .iter()
+                    .take(count(n)?)
+                    .map(|(_, a)| self.jogadores.get(*a).unwrap())
+                    .collect::<Vec<&JogadorComRating>>();
                 Ok(QueryResult::Jogadores(jogadores))
             }
             Query::Tags(tags) => {
@@ -218,25 +630,25 @@ impl DB {
                     .jogadores
                     .tag
                     .get(&first_tag.to_lowercase())
-                    .iter()
+                    .into_iter()
                     .flatten()
                     .map(|a| self.jogadores.get(*a).unwrap())
-                    .collect::<Vec<JogadorComRating>>();
+                    .collect::<Vec<&JogadorComRating>>();
                 for tag in tags_iter {
                     let jogadores = self
                         .jogadores
                         .tag
                         .get(&tag.to_lowercase())
-                        .iter()
+                        .into_iter()
                         .flatten()
                         .map(|a| self.jogadores.get(*a).unwrap())
                         .filter(|a| last_jogadores.contains(a))
-                        .collect::<Vec<JogadorComRating>>();
+                        .collect::<Vec<&JogadorComRating>>();
                     last_jogadores = jogadores;
                 }
                 // Remover duplicados
                 if last_jogadores.len() > 1 {
-                    let mut jogadores = vec![last_jogadores[0].clone()];
+                    let mut jogadores = vec![last_jogadores[0]];
                     for j in last_jogadores {
                         if !jogadores.contains(&j) {
                             jogadores.push(j);
@@ -246,7 +658,312 @@ impl DB {
                 } else {
                     Ok(QueryResult::Jogadores(vec![]))
                 }
-            } // _ => Err(anyhow!("Query not implemented")),
+            }
+            Query::Rank(name, position) => {
+                let btree = self
+                    .jogadores
+                    .pos_ht
+                    .get(&position)
+                    .ok_or_else(|| anyhow!("Position not found"))?;
+                let mut jogadores = self
+                    .search_jogador(name)?
+                    .into_iter()
+                    // Players without enough ratings aren't in the index.
+                    .filter(|a| in_pos_ht(a) && a.get_pos().player_positions.contains(&position))
+                    .map(|a| (btree.rank_rev(&pos_key(a)) + 1, a))
+                    .collect::<Vec<(usize, &JogadorComRating)>>();
+                jogadores.sort_by_key(|a| a.0);
+                Ok(QueryResult::Rank {
+                    position,
+                    total: btree.len(),
+                    jogadores,
+                })
+            }
+            Query::Stats => Ok(QueryResult::Stats(self.stats())),
+            // _ => Err(anyhow!("Query not implemented")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jogadores(csv: &str) -> JogadoresDB {
+        let mut db = JogadoresDB::new();
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        for jogador in reader.deserialize::<Jogador>() {
+            db.insert(&jogador.unwrap()).unwrap();
+        }
+        db
+    }
+
+    fn rate(db: &mut JogadoresDB, id: u32, rating: f64, count: u32) {
+        let sum = RatingSum {
+            sum: rating * f64::from(count),
+            count,
+        };
+        db.add_rating_sum(id, sum).unwrap();
+    }
+
+    const PLAYERS: &str = "sofifa_id,name,player_positions
+1,Lionel Andrés Messi Cuccittini,\"RW, ST, CF\"
+2,Cristiano Ronaldo dos Santos Aveiro,\"ST, LW\"
+3,Neymar da Silva Santos Júnior,\"LW, CAM\"
+4,Son Heung-min,\"LM, CF, LW\"
+5,Silva Costa,CB
+";
+
+    fn rated() -> JogadoresDB {
+        let mut db = jogadores(PLAYERS);
+        rate(&mut db, 1, 4.5, 2000);
+        rate(&mut db, 2, 4.0, 3000);
+        rate(&mut db, 3, 3.5, 500);
+        rate(&mut db, 4, 4.2, 1500);
+        db
+    }
+
+    fn rerate(db: &mut JogadoresDB) {
+        rate(db, 3, 5.0, 600);
+        rate(db, 4, 1.0, 10);
+    }
+
+    #[test]
+    fn save_and_load_pos_ht() {
+        let dir = std::env::temp_dir().join(format!("fifa_sorter_posicoes_{}", std::process::id()));
+        let mut db = rated();
+        db.populate_pos_ht();
+        db.save_pos_ht(&dir).unwrap();
+
+        let mut loaded = rated();
+        loaded.load_pos_ht(&dir).unwrap();
+        for pos in ["RW", "ST", "CF", "LW", "CAM", "LM"] {
+            assert_eq!(
+                loaded.pos_ht.get(&pos.to_string()).unwrap().get_all(),
+                db.pos_ht.get(&pos.to_string()).unwrap().get_all()
+            );
         }
+        let lw = loaded.pos_ht.get(&"LW".to_string()).unwrap();
+        assert!(lw.iter().map(|(_, id)| *id).eq([2, 4]));
+
+        // New ratings make the saved indexes stale until they're saved again.
+        rerate(&mut db);
+        let mut stale = rated();
+        rerate(&mut stale);
+        assert!(stale.load_pos_ht(&dir).is_err());
+
+        db.populate_pos_ht();
+        db.save_pos_ht(&dir).unwrap();
+        stale.load_pos_ht(&dir).unwrap();
+        let lw = stale.pos_ht.get(&"LW".to_string()).unwrap();
+        assert!(lw.iter().map(|(_, id)| *id).eq([2, 4, 3]));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rank_skips_players_not_indexed() {
+        let mut jogadores = rated();
+        jogadores.populate_pos_ht();
+        let db: DB = DB {
+            jogadores,
+            users: UsersDB::new(),
+        };
+
+        let query = Query::Rank("Neymar".to_string(), "LW".to_string());
+        let QueryResult::Rank { jogadores, .. } = db.run_query(query).unwrap() else {
+            panic!("expected a rank");
+        };
+        assert!(jogadores.is_empty());
+
+        let query = Query::Rank("Son".to_string(), "LW".to_string());
+        let QueryResult::Rank {
+            total, jogadores, ..
+        } = db.run_query(query).unwrap()
+        else {
+            panic!("expected a rank");
+        };
+        assert_eq!(total, 2);
+        assert_eq!(jogadores.len(), 1);
+        assert_eq!(jogadores[0].0, 1);
+        assert_eq!(jogadores[0].1.get_sofifa_id(), 4);
+    }
+
+    #[test]
+    fn non_finite_ratings_not_indexed() {
+        let mut db = rated();
+        rate(&mut db, 3, f64::NAN, 1000);
+        db.populate_pos_ht();
+        let lw = db.pos_ht.get(&"LW".to_string()).unwrap();
+        assert!(lw.iter().map(|(_, id)| *id).eq([2, 4]));
+
+        rate(&mut db, 4, f64::INFINITY, 1);
+        let lw = db.pos_ht.get(&"LW".to_string()).unwrap();
+        assert!(lw.iter().map(|(_, id)| *id).eq([2]));
+    }
+
+    #[test]
+    fn top_and_bottom() {
+        let mut jogadores = rated();
+        jogadores.populate_pos_ht();
+        let db: DB = DB {
+            jogadores,
+            users: UsersDB::new(),
+        };
+        let ids = |query| match db.run_query(query).unwrap() {
+            QueryResult::Jogadores(jogadores) => jogadores
+                .into_iter()
+                .map(JogadorComRating::get_sofifa_id)
+                .collect::<Vec<u32>>(),
+            result => panic!("unexpected result {result:?}"),
+        };
+
+        assert_eq!(ids(Query::Top(1, "ST".to_string())), vec![1]);
+        assert_eq!(ids(Query::Top(5, "ST".to_string())), vec![1, 2]);
+        assert_eq!(ids(Query::Bottom(1, "ST".to_string())), vec![2]);
+        assert_eq!(ids(Query::Bottom(5, "CF".to_string())), vec![4, 1]);
+        assert!(ids(Query::Bottom(0, "CF".to_string())).is_empty());
+        assert!(db.run_query(Query::Top(-1, "ST".to_string())).is_err());
+        assert!(db.run_query(Query::Bottom(-1, "ST".to_string())).is_err());
+    }
+
+    fn search(db: &JogadoresDB, name: &str) -> Vec<u32> {
+        db.search(name.to_string())
+            .unwrap()
+            .into_iter()
+            .map(JogadorComRating::get_sofifa_id)
+            .collect()
+    }
+
+    #[test]
+    fn search_by_any_word() {
+        let db = jogadores(PLAYERS);
+        assert_eq!(search(&db, "messi"), vec![1]);
+        assert_eq!(search(&db, "Lionel Andrés"), vec![1]);
+        assert_eq!(search(&db, "cristiano santos"), vec![2]);
+        assert_eq!(search(&db, "santos"), vec![2, 3]);
+        assert!(search(&db, "messi ronaldo").is_empty());
+    }
+
+    #[test]
+    fn search_full_name_first() {
+        let db = jogadores(PLAYERS);
+        // "Silva Costa" starts with the name, Neymar only has a word with it.
+        assert_eq!(search(&db, "silva"), vec![5, 3]);
+    }
+
+    #[test]
+    fn search_matched_twice_listed_once() {
+        let db = jogadores(PLAYERS);
+        assert_eq!(search(&db, "son"), vec![4]);
+        assert_eq!(search(&db, "Son Heung"), vec![4]);
+    }
+
+    #[test]
+    fn search_hyphenated_words() {
+        let db = jogadores(PLAYERS);
+        assert_eq!(search(&db, "min"), vec![4]);
+        assert_eq!(search(&db, "heung-min"), vec![4]);
+        assert_eq!(search(&db, "heung min"), vec![4]);
+    }
+
+    fn suggest(csv: &str, counts: &[(u32, u32)], prefix: &str, k: usize) -> Vec<u32> {
+        let mut db = jogadores(csv);
+        for &(id, count) in counts {
+            rate(&mut db, id, 3.0, count);
+        }
+        db.weight_tries();
+        db.suggest(prefix, k)
+            .into_iter()
+            .map(JogadorComRating::get_sofifa_id)
+            .collect()
+    }
+
+    #[test]
+    fn suggest_player_under_several_keys() {
+        let csv = "sofifa_id,name,player_positions
+1,Zé Sa Sb Sc,ST
+2,Zico Sd,ST
+3,Zeca Se,ST
+4,Zizinho Sf Sg,ST
+";
+        // The three heaviest words belong to the same player.
+        let counts = [(1, 100), (2, 40), (3, 30), (4, 20)];
+        assert_eq!(suggest(csv, &counts, "s", 2), vec![1, 2]);
+        assert_eq!(suggest(csv, &counts, "s", 3), vec![1, 2, 3]);
+        assert_eq!(suggest(csv, &counts, "s", 10), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn suggest_ties_by_id() {
+        let csv = "sofifa_id,name,player_positions
+9,Sa,ST
+3,Sb,ST
+7,Sc,ST
+";
+        let counts = [(9, 50), (3, 50), (7, 50)];
+        assert_eq!(suggest(csv, &counts, "s", 1), vec![3]);
+        assert_eq!(suggest(csv, &counts, "s", 2), vec![3, 7]);
+    }
+
+    #[test]
+    fn finish_init_without_writable_dir() {
+        // A file where the directory should be, so saving fails.
+        let dir = std::env::temp_dir().join(format!("fifa_sorter_arquivo_{}", std::process::id()));
+        fs::write(&dir, "").unwrap();
+        let mut db: DB = DB {
+            jogadores: rated(),
+            users: UsersDB::new(),
+        };
+
+        db.finish_init_in(&dir);
+        let lw = db.jogadores.pos_ht.get(&"LW".to_string()).unwrap();
+        assert!(lw.iter().map(|(_, id)| *id).eq([2, 4]));
+
+        fs::remove_file(dir).unwrap();
+    }
+
+    #[test]
+    fn merge_ratings_in_file_order() {
+        let csv = "user_id,sofifa_id,rating
+7,1,4.0
+8,2,3.0
+7,3,2.5
+7,2,5.0
+8,1,1.0
+";
+        let ratings = csv::Reader::from_reader(csv.as_bytes())
+            .deserialize::<Rating>()
+            .map(Result::unwrap)
+            .collect::<Vec<Rating>>();
+        let ingest = RatingIngest::default();
+        // Added backwards, like a thread that got the later lines first.
+        for (line, rating) in ratings.iter().enumerate().rev() {
+            ingest.add(line, rating).unwrap();
+        }
+
+        let mut db: DB = DB {
+            jogadores: jogadores(PLAYERS),
+            users: UsersDB::new(),
+        };
+        db.merge_ratings(ingest).unwrap();
+        let ids = |user: u32| {
+            db.get_user(user)
+                .unwrap()
+                .get_ratings()
+                .iter()
+                .map(Rating::get_sofifa_id)
+                .collect::<Vec<u32>>()
+        };
+        assert_eq!(ids(7), vec![1, 3, 2]);
+        assert_eq!(ids(8), vec![2, 1]);
+        assert_eq!(db.get_jogador(1).unwrap().get_rating_count(), 2);
+    }
+
+    #[test]
+    fn invalid_pos_path() {
+        assert!(pos_path(Path::new("x"), "ST").is_ok());
+        assert!(pos_path(Path::new("x"), "../ST").is_err());
+        assert!(pos_path(Path::new("x"), "").is_err());
     }
 }
@@ -74,6 +74,14 @@ impl JogadorComRating {
         self.avaliacoes += 1;
     }
 
+    /// Adds `count` ratings summing to `sum` at once.
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn add_ratings(&mut self, sum: f64, count: u32) {
+        let total = f64::from(self.nota) * f64::from(self.avaliacoes) + sum;
+        self.avaliacoes += count;
+        self.nota = (total / f64::from(self.avaliacoes)) as f32;
+    }
+
     pub fn add_tag(&mut self, tag: &Tag) {
         if !self.tags.0.contains(tag.get_tag()) {
             self.tags.0.push(tag.get_tag().clone());
@@ -143,10 +151,10 @@ pub struct User {
 }
 
 impl User {
-    pub fn from_rating(rating: Rating) -> Self {
+    pub fn new(id: u32) -> Self {
         User {
-            ratings: vec![rating.clone()],
-            id: rating.get_user_id(),
+            id,
+            ratings: Vec::new(),
         }
     }
 
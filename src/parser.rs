@@ -3,9 +3,31 @@ use anyhow::{anyhow, Result};
 #[derive(Debug, PartialEq, Eq)]
 pub enum Query {
     Player(String),
+    Similar(String),
+    Glob(String),
+    Suggest(String),
     User(u32),
     Top(i32, String),
+    Bottom(i32, String),
     Tags(Vec<String>),
+    Rank(String, String),
+    Stats,
+}
+
+fn parse_position(pos: &str) -> Result<&str> {
+    //remove the '
+    let pos = pos
+        .strip_prefix('\'')
+        .ok_or_else(|| anyhow::anyhow!("Posição deve começar com aspas simples"))?;
+    pos.strip_suffix('\'')
+        .ok_or_else(|| anyhow::anyhow!("Posição deve terminar com aspas simples"))
+}
+
+fn parse_n_position(n: &str, pos: Option<&str>) -> Result<(i32, String)> {
+    let i = n.parse::<i32>()?;
+    let pos = pos.ok_or_else(|| anyhow::anyhow!("Posição não pode ser vazia"))?;
+    let pos = parse_position(pos)?;
+    Ok((i, pos.to_string()))
 }
 
 fn parse_query(query: &str) -> Result<Query> {
@@ -14,12 +36,39 @@ fn parse_query(query: &str) -> Result<Query> {
         Some("player") => {
             // Add whitespaces between words
             let name: String = query.collect::<Vec<&str>>().join(" ");
-            if name.trim().is_empty() {
+            // player glob:*inho or player /*inho/
+            let pattern = name.strip_prefix("glob:").or_else(|| {
+                name.strip_prefix('/')
+                    .and_then(|name| name.strip_suffix('/'))
+            });
+            if let Some(pattern) = pattern {
+                if pattern.trim().is_empty() {
+                    Err(anyhow!("Padrão não pode ser vazio"))
+                } else {
+                    Ok(Query::Glob(pattern.to_string()))
+                }
+            } else if name.trim().is_empty() {
                 Err(anyhow!("Nome do jogador não pode ser vazio"))
             } else {
                 Ok(Query::Player(name))
             }
         }
+        Some("player~") => {
+            let name: String = query.collect::<Vec<&str>>().join(" ");
+            if name.trim().is_empty() {
+                Err(anyhow!("Nome do jogador não pode ser vazio"))
+            } else {
+                Ok(Query::Similar(name))
+            }
+        }
+        Some("suggest") => {
+            let prefix: String = query.collect::<Vec<&str>>().join(" ");
+            if prefix.trim().is_empty() {
+                Err(anyhow!("Prefixo não pode ser vazio"))
+            } else {
+                Ok(Query::Suggest(prefix))
+            }
+        }
         Some("user") => match query.next() {
             Some(user) => match user.parse::<u32>() {
                 Ok(user) => Ok(Query::User(user)),
@@ -45,27 +94,35 @@ fn parse_query(query: &str) -> Result<Query> {
                 Ok(Query::Tags(tags))
             }
         }
+        Some("rank") => {
+            // Format: rank Lionel Messi 'RW'
+            let args = query.collect::<Vec<&str>>();
+            let (pos, name) = args
+                .split_last()
+                .ok_or_else(|| anyhow!("Nome do jogador não pode ser vazio"))?;
+            let pos = parse_position(pos)?;
+            let name = name.join(" ");
+            if name.trim().is_empty() {
+                Err(anyhow!("Nome do jogador não pode ser vazio"))
+            } else {
+                Ok(Query::Rank(name, pos.to_string()))
+            }
+        }
+        Some("stats") => {
+            if query.next().is_some() {
+                anyhow::bail!("Comando stats não recebe argumentos");
+            }
+            Ok(Query::Stats)
+        }
         Some(prompt) => {
-            if prompt.starts_with("top") {
+            if let Some(prompt) = prompt.strip_prefix("top") {
                 // top10 'ST'
-                let prompt = prompt.strip_prefix("top");
-                if let Some(prompt) = prompt {
-                    let i = prompt.parse::<i32>()?;
-                    let pos = query
-                        .next()
-                        .ok_or_else(|| anyhow::anyhow!("Posição não pode ser vazia"))?;
-                    //remove the '
-                    let pos = pos
-                        .strip_prefix('\'')
-                        .ok_or_else(|| anyhow::anyhow!("Posição deve começar com aspas simples"))?;
-                    let pos = pos.strip_suffix('\'').ok_or_else(|| {
-                        anyhow::anyhow!("Posição deve terminar com aspas simples")
-                    })?;
-
-                    Ok(Query::Top(i, pos.to_string()))
-                } else {
-                    Err(anyhow::anyhow!("Comando inválido"))
-                }
+                let (i, pos) = parse_n_position(prompt, query.next())?;
+                Ok(Query::Top(i, pos))
+            } else if let Some(prompt) = prompt.strip_prefix("bottom") {
+                // bottom10 'ST'
+                let (i, pos) = parse_n_position(prompt, query.next())?;
+                Ok(Query::Bottom(i, pos))
             } else {
                 Err(anyhow::anyhow!("Comando inválido"))
             }
@@ -100,6 +157,22 @@ mod tests {
         let query = parse_query(query).unwrap();
         assert_eq!(query, Query::Player("Cristiano Ronaldo".to_string()));
 
+        let query = "player~ Cristano  Ronaldo";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Similar("Cristano Ronaldo".to_string()));
+
+        let query = "player glob:*inho";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Glob("*inho".to_string()));
+
+        let query = "player /de */";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Glob("de *".to_string()));
+
+        let query = "suggest cristiano ro";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Suggest("cristiano ro".to_string()));
+
         let query = "user 123";
         let query = parse_query(query).unwrap();
         assert_eq!(query, Query::User(123));
@@ -111,6 +184,21 @@ mod tests {
         let query = "top10 'ST'";
         let query = parse_query(query).unwrap();
         assert_eq!(query, Query::Top(10, "ST".to_string()));
+
+        let query = "bottom5 'GK'";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Bottom(5, "GK".to_string()));
+
+        let query = "rank Lionel Messi 'RW'";
+        let query = parse_query(query).unwrap();
+        assert_eq!(
+            query,
+            Query::Rank("Lionel Messi".to_string(), "RW".to_string())
+        );
+
+        let query = "stats";
+        let query = parse_query(query).unwrap();
+        assert_eq!(query, Query::Stats);
     }
 
     #[test]
@@ -138,6 +226,22 @@ mod tests {
         let query = parse_query(query);
         assert!(query.is_err());
 
+        let query = "player~";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "player glob:";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "player //";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "suggest";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
         let query = "user";
         let query = parse_query(query);
         assert!(query.is_err());
@@ -165,5 +269,33 @@ mod tests {
         let query = "top 'ST'";
         let query = parse_query(query);
         assert!(query.is_err());
+
+        let query = "bottom";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "bottom10";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "bottom 'ST'";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "rank";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "rank 'ST'";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "rank Messi ST";
+        let query = parse_query(query);
+        assert!(query.is_err());
+
+        let query = "stats 'ST'";
+        let query = parse_query(query);
+        assert!(query.is_err());
     }
 }
@@ -1,9 +1,16 @@
-use anyhow::Result;
+use std::num::NonZeroUsize;
+use std::sync::{mpsc, Arc, Mutex, PoisonError};
+use std::{mem, thread};
+
+use anyhow::{anyhow, Result};
 use csv::Reader;
 
-use crate::knowledge::DB;
+use crate::knowledge::{RatingIngest, DB};
 use crate::models::*;
 
+// Ratings parsed before handing them to a worker thread.
+const RATING_BATCH: usize = 50_000;
+
 #[allow(dead_code)]
 pub fn read_tags(db: &mut DB) -> Result<(), anyhow::Error> {
     let mut tag_reader = Reader::from_path("data/tags.csv")?;
@@ -34,6 +41,100 @@ pub fn read_rating(db: &mut DB) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
+/// Like [`read_rating`], but the ratings are added by one worker thread per
+/// core while this thread keeps parsing the file.
+#[allow(dead_code)]
+pub fn read_rating_parallel(db: &mut DB) -> Result<(), anyhow::Error> {
+    let mut reader = Reader::from_path("data/rating.csv")?;
+    let ingest = RatingIngest::default();
+    let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+    let ratings = reader
+        .deserialize::<Rating>()
+        .enumerate()
+        .map(|(line, rating)| Ok((line, rating?)));
+    add_parallel(ratings, threads, |(line, rating)| ingest.add(*line, rating))?;
+
+    db.merge_ratings(ingest)
+}
+
+// Hands `items` in batches to `threads` workers running `add` on each one.
+// Stops at the first error, be it reading an item or adding it.
+fn add_parallel<T, I, F>(items: I, threads: usize, add: F) -> Result<(), anyhow::Error>
+where
+    T: Send,
+    I: IntoIterator<Item = Result<T, anyhow::Error>>,
+    F: Fn(&T) -> Result<(), anyhow::Error> + Sync,
+{
+    let (sender, receiver) = mpsc::sync_channel::<Vec<T>>(threads);
+    // Each worker owns a handle to the receiver, so it's dropped once the
+    // last worker quits and sending fails instead of blocking forever.
+    let receiver = Arc::new(Mutex::new(receiver));
+    let add = &add;
+
+    thread::scope(|s| -> Result<(), anyhow::Error> {
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                s.spawn(move || -> Result<(), anyhow::Error> {
+                    loop {
+                        let batch = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        // The sender is gone once every item was read.
+                        let Ok(batch) = batch else {
+                            return Ok(());
+                        };
+                        for item in &batch {
+                            add(item)?;
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        drop(receiver);
+
+        // Dropped on every way out of this closure, so the workers stop.
+        let sender = sender;
+        let mut batch = Vec::with_capacity(RATING_BATCH);
+        let mut read_error = None;
+        for item in items {
+            match item {
+                Ok(item) => batch.push(item),
+                Err(err) => {
+                    read_error = Some(err);
+                    break;
+                }
+            }
+            if batch.len() == RATING_BATCH {
+                let full = mem::replace(&mut batch, Vec::with_capacity(RATING_BATCH));
+                if sender.send(full).is_err() {
+                    break;
+                }
+            }
+        }
+        // A failed send means every worker quit, and the join reports why.
+        if read_error.is_none() {
+            let _ = sender.send(batch);
+        }
+        drop(sender);
+
+        // Join every worker before reporting, or the scope panics over the
+        // ones left behind.
+        let results = workers
+            .into_iter()
+            .map(thread::ScopedJoinHandle::join)
+            .collect::<Vec<_>>();
+        if let Some(err) = read_error {
+            return Err(err);
+        }
+        for result in results {
+            result.map_err(|_| anyhow!("Thread de leitura de avaliações falhou"))??;
+        }
+        Ok(())
+    })
+}
+
 #[allow(dead_code)]
 pub fn read_jogadores(db: &mut DB) -> Result<(), anyhow::Error> {
     let mut reader = Reader::from_path("data/players.csv")?;
@@ -49,9 +150,76 @@ pub fn read_jogadores(db: &mut DB) -> Result<(), anyhow::Error> {
 
 pub fn initialize(db: &mut DB) -> Result<()> {
     read_jogadores(db)?;
-    read_rating(db)?;
+    read_rating_parallel(db)?;
     read_tags(db)?;
 
     db.finish_init();
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn add_parallel_adds_everything() {
+        let total = AtomicUsize::new(0);
+        let items = (0..RATING_BATCH * 3 + 7).map(Ok);
+        add_parallel(items, 4, |i| {
+            total.fetch_add(*i, Ordering::Relaxed);
+            Ok(())
+        })
+        .unwrap();
+        let n = RATING_BATCH * 3 + 7;
+        assert_eq!(total.into_inner(), n * (n - 1) / 2);
+    }
+
+    #[test]
+    fn add_parallel_stops_on_error() {
+        // Far more batches than the channel holds, so a sender that kept
+        // waiting on the failed workers would never return.
+        let items = (0..RATING_BATCH * 20).map(Ok);
+        let result = add_parallel(items, 2, |_: &usize| Err(anyhow!("falhou")));
+        assert_eq!(result.unwrap_err().to_string(), "falhou");
+    }
+
+    #[test]
+    fn add_parallel_stops_on_panic() {
+        let items = (0..RATING_BATCH * 20).map(Ok);
+        let result = add_parallel(items, 2, |_: &usize| -> Result<()> { panic!("falhou") });
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn add_parallel_read_error_after_panic() {
+        // One worker panics on the first batch, the other keeps going until
+        // the bad line, so a worker is already gone when reading fails.
+        let items = (0..RATING_BATCH * 3).map(|i| {
+            if i == RATING_BATCH * 2 {
+                Err(anyhow!("linha ruim"))
+            } else {
+                Ok(i)
+            }
+        });
+        let result = add_parallel(items, 2, |i: &usize| -> Result<()> {
+            assert_ne!(*i, 0, "falhou");
+            Ok(())
+        });
+        assert_eq!(result.unwrap_err().to_string(), "linha ruim");
+    }
+
+    #[test]
+    fn add_parallel_read_error() {
+        let items = (0..10).map(|i| {
+            if i == 5 {
+                Err(anyhow!("linha ruim"))
+            } else {
+                Ok(i)
+            }
+        });
+        let result = add_parallel(items, 2, |_| Ok(()));
+        assert_eq!(result.unwrap_err().to_string(), "linha ruim");
+    }
+}
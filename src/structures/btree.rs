@@ -1,11 +1,18 @@
+use std::collections::VecDeque;
+use std::iter::FusedIterator;
 use std::mem;
 use std::mem::size_of;
+use std::ops::{Bound, RangeBounds};
+
+use anyhow::{bail, Result};
 
 #[derive(Debug, Clone)]
 struct Node<K, V> {
     keys: Vec<K>,
     values: Vec<V>,
     children: Vec<Node<K, V>>,
+    // Number of entries in the subtree rooted at this node.
+    size: usize,
 }
 
 #[derive(Debug, Clone)]
@@ -17,18 +24,31 @@ pub struct BTree<K, V> {
 #[derive(Debug, Clone)]
 struct BTreeProps;
 
-const PAGE: usize = 2048;
+pub(crate) const PAGE: usize = 2048;
 
 impl<K, V> Node<K, V>
 where
-    K: PartialOrd + Clone,
+    K: Ord + Clone,
     V: Clone,
 {
-    const ORDER: usize = (PAGE - size_of::<Node<K, V>>()) / (size_of::<K>() + size_of::<V>());
-    const _MIN_KEYS: usize = (Self::ORDER - 1) / 2;
-    const MAX_KEYS: usize = Self::ORDER - 1;
+    const ORDER: usize = {
+        let order = (PAGE - size_of::<Node<K, V>>()) / (size_of::<K>() + size_of::<V>());
+        // Nodes must be able to hold at least 3 keys, or splitting and
+        // merging can't keep every node within the bounds below.
+        if order < 4 {
+            4
+        } else {
+            order
+        }
+    };
+    // Minimum degree: every node but the root has between DEGREE - 1 and
+    // 2 * DEGREE - 1 keys, so a full node splits into two minimal nodes and
+    // two minimal nodes plus their separator merge back into a full one.
+    const DEGREE: usize = Self::ORDER / 2;
+    const MIN_KEYS: usize = Self::DEGREE - 1;
+    const MAX_KEYS: usize = 2 * Self::DEGREE - 1;
 
-    const MID_KEYS: usize = (Self::ORDER - 1) / 2;
+    const MID_KEYS: usize = Self::DEGREE - 1;
 
     fn new(
         _keys: Option<Vec<K>>,
@@ -36,11 +56,18 @@ where
         _children: Option<Vec<Node<K, V>>>,
     ) -> Self {
         assert!(Self::ORDER > 2);
-        Node {
+        let mut node = Node {
             keys: _keys.unwrap_or_else(|| Vec::with_capacity(Self::MAX_KEYS)),
             values: _values.unwrap_or_else(|| Vec::with_capacity(Self::MAX_KEYS)),
             children: _children.unwrap_or_else(|| Vec::with_capacity(Self::MAX_KEYS + 1)),
-        }
+            size: 0,
+        };
+        node.recount();
+        node
+    }
+
+    fn recount(&mut self) {
+        self.size = self.keys.len() + self.children.iter().map(|c| c.size).sum::<usize>();
     }
 
     fn is_leaf(&self) -> bool {
@@ -83,18 +110,177 @@ where
         }
         res
     }
+
+    /// Appends, in tree order, every value stored under `key`.
+    fn collect_equal<'a>(&'a self, key: &K, res: &mut Vec<&'a V>) {
+        let mut lo = 0;
+        while lo < self.keys.len() && self.keys[lo] < *key {
+            lo += 1;
+        }
+        let mut hi = lo;
+        while hi < self.keys.len() && self.keys[hi] == *key {
+            hi += 1;
+        }
+        for i in lo..=hi {
+            if let Some(child) = self.children.get(i) {
+                child.collect_equal(key, res);
+            }
+            if i < hi {
+                res.push(&self.values[i]);
+            }
+        }
+    }
+
+    /// Appends, in ascending order, every entry whose key lies in `range`,
+    /// skipping the subtrees that fall entirely outside of it.
+    fn collect_range<R: RangeBounds<K>>(&self, range: &R, res: &mut Vec<(K, V)>) {
+        for i in 0..self.keys.len() {
+            if Self::before_start(&self.keys[i], range) {
+                continue;
+            }
+            if !self.is_leaf() {
+                self.children[i].collect_range(range, res);
+            }
+            if Self::after_end(&self.keys[i], range) {
+                return;
+            }
+            res.push((self.keys[i].clone(), self.values[i].clone()));
+        }
+        if !self.is_leaf() {
+            self.children[self.keys.len()].collect_range(range, res);
+        }
+    }
+
+    fn before_start<R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+        match range.start_bound() {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        }
+    }
+
+    fn after_end<R: RangeBounds<K>>(key: &K, range: &R) -> bool {
+        match range.end_bound() {
+            Bound::Included(end) => key > end,
+            Bound::Excluded(end) => key >= end,
+            Bound::Unbounded => false,
+        }
+    }
+
+    /// Checks the invariants of the subtree rooted at this node, whose keys
+    /// must all lie between `lower` and `upper`. Returns the number of
+    /// entries found in the subtree.
+    fn validate(
+        &self,
+        lower: Option<&K>,
+        upper: Option<&K>,
+        depth: usize,
+        leaf_depth: &mut Option<usize>,
+    ) -> Result<usize> {
+        let n = self.keys.len();
+        if self.values.len() != n {
+            bail!(
+                "Node at depth {} has {} keys but {} values",
+                depth,
+                n,
+                self.values.len()
+            );
+        }
+        if n > Self::MAX_KEYS {
+            bail!(
+                "Node at depth {} has {} keys, more than the maximum of {}",
+                depth,
+                n,
+                Self::MAX_KEYS
+            );
+        }
+        if depth > 0 && n < Self::MIN_KEYS {
+            bail!(
+                "Node at depth {} has {} keys, less than the minimum of {}",
+                depth,
+                n,
+                Self::MIN_KEYS
+            );
+        }
+        if let Some(i) = (1..n).find(|&i| self.keys[i - 1] > self.keys[i]) {
+            bail!(
+                "Keys {} and {} of node at depth {} are out of order",
+                i - 1,
+                i,
+                depth
+            );
+        }
+        if lower.is_some_and(|lower| self.keys.first().is_some_and(|k| k < lower)) {
+            bail!(
+                "Node at depth {} has a key smaller than its left separator",
+                depth
+            );
+        }
+        if upper.is_some_and(|upper| self.keys.last().is_some_and(|k| k > upper)) {
+            bail!(
+                "Node at depth {} has a key greater than its right separator",
+                depth
+            );
+        }
+
+        let mut size = n;
+        if self.is_leaf() {
+            match *leaf_depth {
+                Some(expected) if expected != depth => {
+                    bail!(
+                        "Leaf at depth {} but other leaves are at depth {}",
+                        depth,
+                        expected
+                    );
+                }
+                _ => *leaf_depth = Some(depth),
+            }
+        } else {
+            if n == 0 {
+                bail!("Internal node at depth {} has no keys", depth);
+            }
+            if self.children.len() != n + 1 {
+                bail!(
+                    "Node at depth {} has {} keys but {} children",
+                    depth,
+                    n,
+                    self.children.len()
+                );
+            }
+            for (i, child) in self.children.iter().enumerate() {
+                let child_lower = if i == 0 {
+                    lower
+                } else {
+                    Some(&self.keys[i - 1])
+                };
+                let child_upper = if i == n { upper } else { Some(&self.keys[i]) };
+                size += child.validate(child_lower, child_upper, depth + 1, leaf_depth)?;
+            }
+        }
+
+        if self.size != size {
+            bail!(
+                "Node at depth {} records {} entries but holds {}",
+                depth,
+                self.size,
+                size
+            );
+        }
+        Ok(size)
+    }
 }
 
+#[allow(clippy::unused_self)]
 impl BTreeProps {
     fn new() -> Self {
         BTreeProps {}
     }
 
-    fn is_filled<K: PartialOrd + Copy, V: Clone>(&self, node: &Node<K, V>) -> bool {
+    fn is_filled<K: Ord + Copy, V: Clone>(&self, node: &Node<K, V>) -> bool {
         node.keys.len() == Node::<K, V>::MAX_KEYS
     }
 
-    fn split_child<K: PartialOrd + Copy + Default, V: Clone>(
+    fn split_child<K: Ord + Copy + Default, V: Clone>(
         &self,
         parent: &mut Node<K, V>,
         child_index: usize,
@@ -118,6 +304,7 @@ impl BTreeProps {
             None
         };
 
+        child.recount();
         let new_child = Node::new(Some(right_keys), Some(right_values), right_children);
 
         parent.keys.insert(child_index, middle_key);
@@ -125,12 +312,13 @@ impl BTreeProps {
         parent.children.insert(child_index + 1, new_child);
     }
 
-    fn insert_non_full<K: PartialOrd + Copy + Default, V: Clone>(
+    fn insert_non_full<K: Ord + Copy + Default, V: Clone>(
         &self,
         node: &mut Node<K, V>,
         key: K,
         value: V,
     ) {
+        node.size += 1;
         if node.is_leaf() {
             let mut i = node.keys.len();
             while i > 0 && key < node.keys[i - 1] {
@@ -145,18 +333,178 @@ impl BTreeProps {
             }
             if self.is_filled(&node.children[i]) {
                 self.split_child(node, i);
-                if key > node.keys[i] {
+                // Equal keys go right, so duplicates keep insertion order.
+                if key >= node.keys[i] {
                     i += 1;
                 }
             }
             self.insert_non_full(&mut node.children[i], key, value);
         }
     }
+
+    /// Removes one entry matching both `key` and `value` from the subtree
+    /// rooted at `node`. Children that underflow on the way back up are
+    /// rebalanced, but `node` itself may be left with too few keys.
+    fn remove_from<K: Ord + Copy + Default, V: Clone + PartialEq>(
+        &self,
+        node: &mut Node<K, V>,
+        key: &K,
+        value: &V,
+    ) -> Option<V> {
+        // Duplicate keys may be spread over every separator equal to `key`
+        // and over all the children around them.
+        let mut lo = 0;
+        while lo < node.keys.len() && node.keys[lo] < *key {
+            lo += 1;
+        }
+        let mut hi = lo;
+        while hi < node.keys.len() && node.keys[hi] <= *key {
+            hi += 1;
+        }
+
+        for i in lo..hi {
+            if node.keys[i] == *key && node.values[i] == *value {
+                node.size -= 1;
+                if node.is_leaf() {
+                    node.keys.remove(i);
+                    return Some(node.values.remove(i));
+                }
+                let (pred_key, pred_value) = self.remove_max(&mut node.children[i]);
+                node.keys[i] = pred_key;
+                let removed = mem::replace(&mut node.values[i], pred_value);
+                self.fix_child(node, i);
+                return Some(removed);
+            }
+        }
+
+        if node.is_leaf() {
+            return None;
+        }
+        for i in lo..=hi {
+            if let Some(removed) = self.remove_from(&mut node.children[i], key, value) {
+                node.size -= 1;
+                self.fix_child(node, i);
+                return Some(removed);
+            }
+        }
+        None
+    }
+
+    fn remove_max<K: Ord + Copy + Default, V: Clone>(&self, node: &mut Node<K, V>) -> (K, V) {
+        node.size -= 1;
+        if node.is_leaf() {
+            let key = node.keys.pop().expect("remove_max on an empty node");
+            let value = node.values.pop().expect("remove_max on an empty node");
+            (key, value)
+        } else {
+            let last = node.children.len() - 1;
+            let res = self.remove_max(&mut node.children[last]);
+            self.fix_child(node, last);
+            res
+        }
+    }
+
+    /// Restores the minimum fill of `parent.children[child_index]`, either by
+    /// borrowing a key through the parent from a sibling or by merging it
+    /// with one.
+    fn fix_child<K: Ord + Copy + Default, V: Clone>(
+        &self,
+        parent: &mut Node<K, V>,
+        child_index: usize,
+    ) {
+        if parent.children[child_index].keys.len() >= Node::<K, V>::MIN_KEYS {
+            return;
+        }
+        if child_index > 0 && parent.children[child_index - 1].keys.len() > Node::<K, V>::MIN_KEYS {
+            self.rotate_right(parent, child_index - 1);
+        } else if child_index + 1 < parent.children.len()
+            && parent.children[child_index + 1].keys.len() > Node::<K, V>::MIN_KEYS
+        {
+            self.rotate_left(parent, child_index);
+        } else if child_index > 0 {
+            self.merge_children(parent, child_index - 1);
+        } else {
+            self.merge_children(parent, child_index);
+        }
+    }
+
+    /// Moves the last key of `children[index]` up into the parent and the
+    /// separator down into the front of `children[index + 1]`.
+    fn rotate_right<K: Ord + Copy + Default, V: Clone>(
+        &self,
+        parent: &mut Node<K, V>,
+        index: usize,
+    ) {
+        let (left, right) = parent.children.split_at_mut(index + 1);
+        let left = &mut left[index];
+        let right = &mut right[0];
+
+        let key = left.keys.pop().expect("rotate_right from an empty node");
+        let value = left.values.pop().expect("rotate_right from an empty node");
+        let sep_key = mem::replace(&mut parent.keys[index], key);
+        let sep_value = mem::replace(&mut parent.values[index], value);
+        right.keys.insert(0, sep_key);
+        right.values.insert(0, sep_value);
+        let mut moved = 1;
+        if let Some(child) = left.children.pop() {
+            moved += child.size;
+            right.children.insert(0, child);
+        }
+        left.size -= moved;
+        right.size += moved;
+    }
+
+    /// Moves the first key of `children[index + 1]` up into the parent and
+    /// the separator down into the back of `children[index]`.
+    fn rotate_left<K: Ord + Copy + Default, V: Clone>(
+        &self,
+        parent: &mut Node<K, V>,
+        index: usize,
+    ) {
+        let (left, right) = parent.children.split_at_mut(index + 1);
+        let left = &mut left[index];
+        let right = &mut right[0];
+
+        let key = right.keys.remove(0);
+        let value = right.values.remove(0);
+        let sep_key = mem::replace(&mut parent.keys[index], key);
+        let sep_value = mem::replace(&mut parent.values[index], value);
+        left.keys.push(sep_key);
+        left.values.push(sep_value);
+        let mut moved = 1;
+        if !right.is_leaf() {
+            let child = right.children.remove(0);
+            moved += child.size;
+            left.children.push(child);
+        }
+        left.size += moved;
+        right.size -= moved;
+    }
+
+    /// Merges `children[index + 1]` and the separator between them into
+    /// `children[index]`.
+    fn merge_children<K: Ord + Copy + Default, V: Clone>(
+        &self,
+        parent: &mut Node<K, V>,
+        index: usize,
+    ) {
+        let right = parent.children.remove(index + 1);
+        let sep_key = parent.keys.remove(index);
+        let sep_value = parent.values.remove(index);
+
+        let left = &mut parent.children[index];
+        left.size += 1 + right.size;
+        left.keys.push(sep_key);
+        left.values.push(sep_value);
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+    }
 }
 
 impl<K, V> Default for BTree<K, V>
 where
-    K: PartialOrd + Copy + Default,
+    K: Ord + Copy + Default,
     V: Default + Clone,
 {
     fn default() -> Self {
@@ -169,7 +517,7 @@ where
 
 impl<K, V> BTree<K, V>
 where
-    K: PartialOrd + Copy + Default,
+    K: Ord + Copy + Default,
     V: Default + Clone,
 {
     pub fn new() -> Self {
@@ -179,16 +527,90 @@ where
         }
     }
 
+    /// Builds a tree bottom-up from entries already sorted by key, packing
+    /// every node as full as the B-tree bounds allow. Much faster than
+    /// inserting the entries one by one, and the result is denser.
+    ///
+    /// If the entries aren't sorted the resulting tree is meaningless.
+    pub fn from_sorted_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+    {
+        let entries = iter.into_iter().collect::<Vec<(K, V)>>();
+        if entries.is_empty() {
+            return Self::new();
+        }
+        // Each node holds at most MAX_KEYS keys, that is, MAX_KEYS + 1
+        // children or leaf entries plus separators.
+        let fanout = Node::<K, V>::MAX_KEYS + 1;
+
+        // Split the entries into as few leaves as possible, spreading them
+        // evenly. Every leaf but the last is followed by a separator that
+        // moves up to the next level.
+        let leaves = (entries.len() + 1).div_ceil(fanout);
+        let leaf_keys = entries.len() - (leaves - 1);
+        let mut entries = entries.into_iter();
+        let mut level = Vec::with_capacity(leaves);
+        let mut separators = Vec::with_capacity(leaves - 1);
+        for i in 0..leaves {
+            let size = leaf_keys / leaves + usize::from(i < leaf_keys % leaves);
+            let (keys, values) = entries.by_ref().take(size).unzip();
+            level.push(Node::new(Some(keys), Some(values), None));
+            if i + 1 < leaves {
+                separators.extend(entries.next());
+            }
+        }
+
+        // Group the nodes of each level under as few parents as possible,
+        // until a single root is left.
+        while level.len() > 1 {
+            let count = level.len();
+            let parents = count.div_ceil(fanout);
+            let mut children = level.into_iter();
+            let mut keys_iter = separators.into_iter();
+            level = Vec::with_capacity(parents);
+            separators = Vec::with_capacity(parents - 1);
+            for i in 0..parents {
+                let size = count / parents + usize::from(i < count % parents);
+                let node_children = children.by_ref().take(size).collect();
+                let (keys, values) = keys_iter.by_ref().take(size - 1).unzip();
+                level.push(Node::new(Some(keys), Some(values), Some(node_children)));
+                if i + 1 < parents {
+                    separators.extend(keys_iter.next());
+                }
+            }
+        }
+
+        BTree {
+            root: level.pop().unwrap_or_else(|| Node::new(None, None, None)),
+            props: BTreeProps::new(),
+        }
+    }
+
     pub fn insert(&mut self, key: K, value: V) {
         if self.props.is_filled(&self.root) {
             let mut new_root = Node::new(None, None, None);
             mem::swap(&mut new_root, &mut self.root);
             self.root.children.push(new_root);
+            self.root.recount();
             self.props.split_child(&mut self.root, 0);
         }
         self.props.insert_non_full(&mut self.root, key, value);
     }
 
+    /// Removes one entry with the given key and value, returning the removed
+    /// value, or `None` if no such entry exists.
+    pub fn remove(&mut self, key: &K, value: &V) -> Option<V>
+    where
+        V: PartialEq,
+    {
+        let removed = self.props.remove_from(&mut self.root, key, value);
+        if self.root.keys.is_empty() && !self.root.is_leaf() {
+            self.root = self.root.children.remove(0);
+        }
+        removed
+    }
+
     pub fn find(&self, key: K) -> Option<&V> {
         let mut node = &self.root;
         loop {
@@ -206,17 +628,142 @@ where
         }
     }
 
+    /// Returns every value stored under `key`, in insertion order for
+    /// duplicates. [`BTree::find`] only returns one of them.
+    #[must_use]
+    pub fn find_all(&self, key: K) -> Vec<&V> {
+        let mut res = Vec::new();
+        self.root.collect_equal(&key, &mut res);
+        res
+    }
+
+    /// Number of values stored under `key`.
+    #[must_use]
+    pub fn count(&self, key: K) -> usize {
+        self.len() - self.rank(&key) - self.rank_rev(&key)
+    }
+
+    /// Removes a single `(key, value)` pair, leaving the other values stored
+    /// under `key` and other copies of the same pair in place. Same as
+    /// [`BTree::remove`], named after the rest of the multimap API.
+    pub fn remove_one(&mut self, key: &K, value: &V) -> Option<V>
+    where
+        V: PartialEq,
+    {
+        self.remove(key, value)
+    }
+
+    #[must_use]
     pub fn get_all(&self) -> Vec<(K, V)> {
-        let mut result = Vec::new();
+        self.iter().map(|(k, v)| (*k, v.clone())).collect()
+    }
+
+    /// Checks the structural invariants of the tree: keys in order and
+    /// within their separators, node fill between the minimum and maximum
+    /// number of keys, one more child than keys in internal nodes, every
+    /// leaf at the same depth and subtree sizes matching the entries.
+    ///
+    /// # Errors
+    /// Returns a description of the first violated invariant.
+    pub fn validate(&self) -> Result<()> {
+        self.root.validate(None, None, 0, &mut None)?;
+        Ok(())
+    }
+
+    /// Number of entries stored in the tree.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.root.size
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.root.size == 0
+    }
+
+    /// Number of entries whose key is strictly smaller than `key`, i.e. the
+    /// 0-based ascending position `key` would take in the tree.
+    #[must_use]
+    pub fn rank(&self, key: &K) -> usize {
         let mut node = &self.root;
+        let mut count = 0;
         loop {
-            for i in 0..node.keys.len() {
-                result.push((node.keys[i], node.values[i].clone()));
+            let mut i = 0;
+            while i < node.keys.len() && node.keys[i] < *key {
+                count += 1 + node.children.get(i).map_or(0, |c| c.size);
+                i += 1;
             }
-            if node.is_leaf() {
-                return result;
+            match node.children.get(i) {
+                Some(child) => node = child,
+                None => return count,
+            }
+        }
+    }
+
+    /// Number of entries whose key is strictly greater than `key`, i.e. the
+    /// 0-based descending position `key` would take in the tree.
+    #[must_use]
+    pub fn rank_rev(&self, key: &K) -> usize {
+        let mut node = &self.root;
+        let mut count = 0;
+        loop {
+            let mut i = node.keys.len();
+            while i > 0 && node.keys[i - 1] > *key {
+                count += 1 + node.children.get(i).map_or(0, |c| c.size);
+                i -= 1;
+            }
+            match node.children.get(i) {
+                Some(child) => node = child,
+                None => return count,
             }
-            node = &node.children[node.keys.len()];
+        }
+    }
+
+    /// Returns the entry at the 0-based ascending position `index`.
+    #[must_use]
+    pub fn select(&self, index: usize) -> Option<(&K, &V)> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut node = &self.root;
+        let mut index = index;
+        'descend: loop {
+            for i in 0..node.keys.len() {
+                let left = node.children.get(i).map_or(0, |c| c.size);
+                if index < left {
+                    node = &node.children[i];
+                    continue 'descend;
+                }
+                if index == left {
+                    return Some((&node.keys[i], &node.values[i]));
+                }
+                index -= left + 1;
+            }
+            node = node.children.last()?;
+        }
+    }
+
+    /// Returns the entry at the `p`-th percentile (0 to 100) of the keys,
+    /// using the nearest-rank method.
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn percentile(&self, p: f64) -> Option<(&K, &V)> {
+        if self.is_empty() {
+            return None;
+        }
+        let rank = (p / 100.0 * self.len() as f64).ceil().max(1.0) as usize;
+        self.select(rank.min(self.len()) - 1)
+    }
+
+    /// Lazily walks the entries in ascending key order. Use `.rev()` for
+    /// descending order.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        let mut pending = VecDeque::new();
+        pending.push_back(Pending::Node(&self.root));
+        Iter {
+            pending,
+            remaining: self.root.size,
         }
     }
 
@@ -227,13 +774,207 @@ where
             .map(|(_, v)| v)
             .collect()
     }
+
+    /// Returns the values of the `n` smallest keys, in ascending order.
+    #[must_use]
+    pub fn get_smallest_n(&self, n: u32) -> Vec<V> {
+        self.iter()
+            .take(n as usize)
+            .map(|(_, v)| v.clone())
+            .collect()
+    }
+
+    /// Returns the values whose keys fall inside `range`, in ascending key
+    /// order, e.g. `tree.range(OrdF32(3.5)..=OrdF32(4.2))`.
+    #[must_use]
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Vec<V> {
+        let mut res = Vec::new();
+        self.root.collect_range(&range, &mut res);
+        res.into_iter().map(|(_, v)| v).collect()
+    }
+
+    /// Same as [`BTree::range`], but in descending key order.
+    #[must_use]
+    pub fn range_rev<R: RangeBounds<K>>(&self, range: R) -> Vec<V> {
+        let mut res = self.range(range);
+        res.reverse();
+        res
+    }
+}
+
+/// A subtree that hasn't been expanded yet, or an entry ready to be yielded.
+enum Pending<N, K, V> {
+    Entry(K, V),
+    Node(N),
+}
+
+impl<N, K, V> Pending<N, K, V> {
+    /// Pops entries from the front of `pending`, expanding subtrees in place
+    /// until an entry comes up.
+    fn next_front<F>(pending: &mut VecDeque<Self>, expand: F) -> Option<(K, V)>
+    where
+        F: Fn(N) -> Vec<Self>,
+    {
+        loop {
+            match pending.pop_front()? {
+                Pending::Entry(k, v) => return Some((k, v)),
+                Pending::Node(node) => {
+                    for item in expand(node).into_iter().rev() {
+                        pending.push_front(item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn next_back<F>(pending: &mut VecDeque<Self>, expand: F) -> Option<(K, V)>
+    where
+        F: Fn(N) -> Vec<Self>,
+    {
+        loop {
+            match pending.pop_back()? {
+                Pending::Entry(k, v) => return Some((k, v)),
+                Pending::Node(node) => pending.extend(expand(node)),
+            }
+        }
+    }
+}
+
+/// Borrowing in-order iterator over a [`BTree`], created by [`BTree::iter`].
+pub struct Iter<'a, K, V> {
+    pending: VecDeque<Pending<&'a Node<K, V>, &'a K, &'a V>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iter<'a, K, V> {
+    fn expand(node: &'a Node<K, V>) -> Vec<Pending<&'a Node<K, V>, &'a K, &'a V>> {
+        let mut items = Vec::with_capacity(node.keys.len() + node.children.len());
+        for (i, (k, v)) in node.keys.iter().zip(&node.values).enumerate() {
+            if let Some(child) = node.children.get(i) {
+                items.push(Pending::Node(child));
+            }
+            items.push(Pending::Entry(k, v));
+        }
+        if let Some(child) = node.children.last() {
+            items.push(Pending::Node(child));
+        }
+        items
+    }
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = Pending::next_front(&mut self.pending, Self::expand);
+        if res.is_some() {
+            self.remaining -= 1;
+        }
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for Iter<'_, K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let res = Pending::next_back(&mut self.pending, Self::expand);
+        if res.is_some() {
+            self.remaining -= 1;
+        }
+        res
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// Owning in-order iterator over a [`BTree`], created by `into_iter`.
+pub struct IntoIter<K, V> {
+    pending: VecDeque<Pending<Node<K, V>, K, V>>,
+    remaining: usize,
+}
+
+impl<K, V> IntoIter<K, V> {
+    fn expand(node: Node<K, V>) -> Vec<Pending<Node<K, V>, K, V>> {
+        let mut items = Vec::with_capacity(node.keys.len() + node.children.len());
+        let mut children = node.children.into_iter();
+        for (k, v) in node.keys.into_iter().zip(node.values) {
+            if let Some(child) = children.next() {
+                items.push(Pending::Node(child));
+            }
+            items.push(Pending::Entry(k, v));
+        }
+        items.extend(children.map(Pending::Node));
+        items
+    }
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = Pending::next_front(&mut self.pending, Self::expand);
+        if res.is_some() {
+            self.remaining -= 1;
+        }
+        res
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> DoubleEndedIterator for IntoIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let res = Pending::next_back(&mut self.pending, Self::expand);
+        if res.is_some() {
+            self.remaining -= 1;
+        }
+        res
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+impl<K, V> IntoIterator for BTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let remaining = self.root.size;
+        let mut pending = VecDeque::new();
+        pending.push_back(Pending::Node(self.root));
+        IntoIter { pending, remaining }
+    }
+}
+
+impl<'a, K, V> IntoIterator for &'a BTree<K, V>
+where
+    K: Ord + Copy + Default,
+    V: Default + Clone,
+{
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::fmt::Debug;
 
     use super::*;
+    use crate::structures::ord_float::OrdF32;
 
     #[derive(Clone, Eq, PartialEq)]
     struct BigStruct {
@@ -252,6 +993,132 @@ mod tests {
         }
     }
 
+    /// Value big enough to force the smallest node order, so random tests
+    /// get deep trees with lots of splits and merges.
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Wide {
+        id: i32,
+        pad: [u8; 500],
+    }
+
+    impl Default for Wide {
+        fn default() -> Self {
+            Wide::new(0)
+        }
+    }
+
+    impl Wide {
+        fn new(id: i32) -> Self {
+            Wide { id, pad: [0; 500] }
+        }
+    }
+
+    /// Small xorshift generator, so random tests are reproducible.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: i32) -> i32 {
+            i32::try_from(self.next() % u64::try_from(n).unwrap()).unwrap()
+        }
+    }
+
+    /// Runs random inserts, removes and queries against a `BTreeMap` used as
+    /// a multiset of `(key, value)` pairs, validating the tree as it goes.
+    fn random_ops<V, F>(seed: u64, ops: usize, keys: i32, make_value: F)
+    where
+        V: Default + Clone + Ord + Debug,
+        F: Fn(i32) -> V,
+    {
+        let mut rng = Rng(seed);
+        let mut tree = BTree::new();
+        let mut oracle: BTreeMap<(i32, V), usize> = BTreeMap::new();
+
+        for op in 0..ops {
+            let key = rng.below(keys);
+            let value = make_value(rng.below(4));
+            if rng.below(3) == 0 {
+                let expected = oracle.get(&(key, value.clone())).copied().unwrap_or(0);
+                let removed = tree.remove(&key, &value);
+                assert_eq!(removed.is_some(), expected > 0, "op {op}");
+                if expected == 1 {
+                    oracle.remove(&(key, value));
+                } else if expected > 1 {
+                    oracle.insert((key, value), expected - 1);
+                }
+            } else {
+                tree.insert(key, value.clone());
+                *oracle.entry((key, value)).or_insert(0) += 1;
+            }
+
+            if op % 50 == 0 {
+                tree.validate().unwrap();
+                check_against_oracle(&tree, &oracle, rng.below(keys), rng.below(keys));
+            }
+        }
+        tree.validate().unwrap();
+        check_against_oracle(&tree, &oracle, 0, keys);
+    }
+
+    fn check_against_oracle<V: Default + Clone + Ord + Debug>(
+        tree: &BTree<i32, V>,
+        oracle: &BTreeMap<(i32, V), usize>,
+        a: i32,
+        b: i32,
+    ) {
+        let expected = oracle
+            .iter()
+            .flat_map(|((k, v), count)| std::iter::repeat_n((*k, v.clone()), *count))
+            .collect::<Vec<_>>();
+
+        let mut entries = tree.get_all();
+        assert!(entries.windows(2).all(|w| w[0].0 <= w[1].0));
+        entries.sort();
+        assert_eq!(entries, expected);
+        assert_eq!(tree.len(), expected.len());
+
+        let (lo, hi) = (a.min(b), a.max(b));
+        let in_range = expected
+            .iter()
+            .filter(|(k, _)| (lo..=hi).contains(k))
+            .count();
+        assert_eq!(tree.range(lo..=hi).len(), in_range);
+        assert_eq!(
+            tree.rank(&lo),
+            expected.iter().filter(|(k, _)| *k < lo).count()
+        );
+        assert_eq!(
+            tree.rank_rev(&hi),
+            expected.iter().filter(|(k, _)| *k > hi).count()
+        );
+        assert_eq!(
+            tree.find(lo).is_some(),
+            expected.iter().any(|(k, _)| *k == lo)
+        );
+        let mut equal = tree.find_all(lo);
+        equal.sort();
+        let expected_equal = expected.iter().filter(|(k, _)| *k == lo).map(|(_, v)| v);
+        assert!(equal.into_iter().eq(expected_equal));
+        assert_eq!(
+            tree.count(hi),
+            expected.iter().filter(|(k, _)| *k == hi).count()
+        );
+
+        let greatest = tree.get_greatest_n(10);
+        assert_eq!(greatest.len(), expected.len().min(10));
+        let smallest = tree.get_smallest_n(10);
+        assert_eq!(smallest.len(), expected.len().min(10));
+        if let Some(i) = expected.len().checked_sub(1) {
+            assert_eq!(tree.select(i).map(|(k, _)| *k), Some(expected[i].0));
+        }
+    }
+
     #[test]
     fn insert() {
         let mut tree = BTree::new();
@@ -280,7 +1147,7 @@ mod tests {
             "Order: {:#?} - MAX: {} - MIN : {}",
             Node::<i32, BigStruct>::ORDER,
             Node::<i32, BigStruct>::MAX_KEYS,
-            Node::<i32, BigStruct>::_MIN_KEYS
+            Node::<i32, BigStruct>::MIN_KEYS
         );
 
         println!("{:#?}", tree);
@@ -318,7 +1185,7 @@ mod tests {
             "Order: {:#?} - MAX: {} - MIN : {}",
             Node::<i32, BigStruct>::ORDER,
             Node::<i32, BigStruct>::MAX_KEYS,
-            Node::<i32, BigStruct>::_MIN_KEYS
+            Node::<i32, BigStruct>::MIN_KEYS
         );
 
         assert_eq!(tree.find(1), Some(&BigStruct { a: [2; 500] }));
@@ -347,6 +1214,51 @@ mod tests {
         assert_eq!(tree.get_greatest_n(0), vec![]);
     }
 
+    #[test]
+    fn get_smallest_n() {
+        let mut tree = BTree::new();
+        tree.insert(1, 1);
+        tree.insert(2, 2);
+        tree.insert(3, 3);
+        tree.insert(4, 4);
+        tree.insert(5, 5);
+
+        assert_eq!(tree.get_smallest_n(3), vec![1, 2, 3]);
+        assert_eq!(tree.get_smallest_n(5), vec![1, 2, 3, 4, 5]);
+        assert_eq!(tree.get_smallest_n(6), vec![1, 2, 3, 4, 5]);
+        assert_eq!(tree.get_smallest_n(0), vec![]);
+        assert_eq!(BTree::<i32, i32>::new().get_smallest_n(3), vec![]);
+    }
+
+    #[test]
+    fn get_smallest_n_duplicate_keys() {
+        let mut tree = BTree::new();
+        for i in 0..300 {
+            tree.insert(i % 3, i);
+        }
+
+        // Equal keys come out in insertion order.
+        let smallest = tree.get_smallest_n(150);
+        assert!(smallest.iter().copied().eq((0..300)
+            .step_by(3)
+            .take(100)
+            .chain((1..300).step_by(3).take(50))));
+        assert_eq!(tree.get_smallest_n(1000).len(), 300);
+    }
+
+    #[test]
+    fn get_smallest_n_big() {
+        let mut tree = BTree::new();
+        for i in (0..100u8).rev() {
+            tree.insert(i32::from(i), BigStruct { a: [i; 500] });
+        }
+
+        let smallest = tree.get_smallest_n(20);
+        assert_eq!(smallest.len(), 20);
+        assert!(smallest.iter().map(|b| b.a[0]).eq(0..20));
+        assert_eq!(tree.get_smallest_n(1), vec![BigStruct { a: [0; 500] }]);
+    }
+
     #[test]
     fn get_greatest_n_on_empty_tree() {
         let tree = BTree::<i32, i32>::new();
@@ -370,7 +1282,7 @@ mod tests {
             "Order: {:#?} - MAX: {} - MIN : {}",
             Node::<i32, BigStruct>::ORDER,
             Node::<i32, BigStruct>::MAX_KEYS,
-            Node::<i32, BigStruct>::_MIN_KEYS
+            Node::<i32, BigStruct>::MIN_KEYS
         );
 
         assert_eq!(
@@ -395,4 +1307,400 @@ mod tests {
 
         assert_eq!(tree.get_greatest_n(1), vec![BigStruct { a: [9; 500] }]);
     }
+
+    #[test]
+    fn remove() {
+        let mut tree = BTree::new();
+        tree.insert(1, 1);
+        tree.insert(2, 2);
+        tree.insert(3, 3);
+
+        assert_eq!(tree.remove(&2, &2), Some(2));
+        assert_eq!(tree.remove(&2, &2), None);
+        assert_eq!(tree.remove(&3, &4), None);
+        assert_eq!(tree.find(2), None);
+        assert_eq!(tree.get_greatest_n(3), vec![3, 1]);
+    }
+
+    #[test]
+    fn remove_duplicate_keys() {
+        let mut tree = BTree::new();
+        for i in 0..1000 {
+            tree.insert(i % 10, i);
+        }
+        for i in (0..1000).step_by(2) {
+            assert_eq!(tree.remove(&(i % 10), &i), Some(i));
+        }
+        for i in 0..1000 {
+            let expected = if i % 2 == 0 { None } else { Some(i) };
+            assert_eq!(tree.remove(&(i % 10), &i), expected);
+        }
+        assert_eq!(tree.get_greatest_n(1), vec![]);
+    }
+
+    #[test]
+    fn remove_many() {
+        let mut tree = BTree::new();
+        for i in 0..5000 {
+            tree.insert((i * 7919) % 5000, i);
+        }
+        for i in (0..5000).filter(|i| i % 3 != 0) {
+            assert_eq!(tree.remove(&((i * 7919) % 5000), &i), Some(i));
+        }
+        for i in 0..5000 {
+            let key = (i * 7919) % 5000;
+            if i % 3 == 0 {
+                assert_eq!(tree.find(key), Some(&i));
+            } else {
+                assert_eq!(tree.find(key), None);
+            }
+        }
+        assert_eq!(tree.get_greatest_n(5000).len(), 1667);
+    }
+
+    #[test]
+    fn remove_big() {
+        let mut tree = BTree::new();
+        for i in 0..100u8 {
+            tree.insert(i32::from(i), BigStruct { a: [i; 500] });
+        }
+        for i in (0..100u8).rev().step_by(3) {
+            assert_eq!(
+                tree.remove(&i32::from(i), &BigStruct { a: [i; 500] }),
+                Some(BigStruct { a: [i; 500] })
+            );
+        }
+        for i in 0..100u8 {
+            if (99 - i) % 3 == 0 {
+                assert_eq!(tree.find(i32::from(i)), None);
+            } else {
+                assert_eq!(tree.find(i32::from(i)), Some(&BigStruct { a: [i; 500] }));
+            }
+        }
+        assert_eq!(tree.get_greatest_n(100).len(), 66);
+    }
+
+    #[test]
+    fn range() {
+        let mut tree = BTree::new();
+        for i in 0..10 {
+            tree.insert(i, i * 10);
+        }
+
+        assert_eq!(tree.range(3..=5), vec![30, 40, 50]);
+        assert_eq!(tree.range(3..5), vec![30, 40]);
+        assert_eq!(tree.range(..2), vec![0, 10]);
+        assert_eq!(tree.range(8..), vec![80, 90]);
+        assert_eq!(tree.range(..).len(), 10);
+        assert_eq!(tree.range(20..30), vec![]);
+        assert_eq!(tree.range_rev(3..=5), vec![50, 40, 30]);
+    }
+
+    #[test]
+    fn range_float_keys() {
+        let mut tree = BTree::new();
+        let ratings = [3.0, 3.5, 3.6, 4.2, 4.25, 4.9, 3.5, f32::NAN];
+        for (id, rating) in ratings.into_iter().enumerate() {
+            tree.insert(OrdF32(rating), id);
+        }
+
+        assert_eq!(tree.range(OrdF32(3.5)..=OrdF32(4.2)).len(), 4);
+        assert_eq!(tree.range_rev(OrdF32(3.5)..=OrdF32(4.2))[0], 3);
+        assert_eq!(tree.range(OrdF32(4.21)..OrdF32(5.0)), vec![4, 5]);
+        assert_eq!(tree.get_greatest_n(2), vec![7, 5]);
+    }
+
+    #[test]
+    fn composite_keys() {
+        // (rating, rating count, sofifa id), ties broken by the later fields
+        let mut tree = BTree::new();
+        tree.insert((OrdF32(4.0), 1200, 7), 7);
+        tree.insert((OrdF32(4.0), 1500, 3), 3);
+        tree.insert((OrdF32(4.5), 1001, 9), 9);
+        tree.insert((OrdF32(4.0), 1200, 2), 2);
+
+        assert_eq!(tree.get_greatest_n(4), vec![9, 3, 7, 2]);
+        assert_eq!(tree.remove(&(OrdF32(4.0), 1200, 7), &7), Some(7));
+        assert_eq!(tree.rank_rev(&(OrdF32(4.0), 1200, 2)), 2);
+    }
+
+    #[test]
+    fn range_many() {
+        let mut tree = BTree::new();
+        for i in 0..5000 {
+            tree.insert((i * 7919) % 5000, i);
+            tree.insert((i * 7919) % 5000, i);
+        }
+
+        let res = tree.range(1000..2000);
+        assert_eq!(res.len(), 2000);
+        for (key, chunk) in (1000..).zip(res.chunks(2)) {
+            assert_eq!(chunk[0], chunk[1]);
+            assert_eq!((chunk[0] * 7919) % 5000, key);
+        }
+        assert_eq!(tree.range_rev(4998..).len(), 4);
+    }
+
+    #[test]
+    fn get_all() {
+        let mut tree = BTree::new();
+        for i in (0..1000).rev() {
+            tree.insert(i, i * 2);
+        }
+
+        let all = tree.get_all();
+        assert_eq!(all.len(), 1000);
+        assert!(all
+            .iter()
+            .enumerate()
+            .all(|(i, (k, v))| *k as usize == i && *v == k * 2));
+    }
+
+    #[test]
+    fn iter() {
+        let mut tree = BTree::new();
+        for i in 0..5000 {
+            tree.insert((i * 7919) % 5000, i);
+        }
+        tree.remove(&0, &0);
+
+        assert_eq!(tree.len(), 4999);
+        assert_eq!(tree.iter().len(), 4999);
+        assert!(tree.iter().map(|(k, _)| *k).eq(1..5000));
+        assert!(tree.iter().rev().map(|(k, _)| *k).eq((1..5000).rev()));
+        assert!((&tree).into_iter().all(|(k, v)| (v * 7919) % 5000 == *k));
+    }
+
+    #[test]
+    fn iter_both_ends() {
+        let mut tree = BTree::new();
+        for i in 0..1000 {
+            tree.insert(i, i);
+        }
+
+        let mut iter = tree.iter();
+        for i in 0..500 {
+            assert_eq!(iter.next(), Some((&i, &i)));
+            assert_eq!(iter.next_back(), Some((&(999 - i), &(999 - i))));
+        }
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.next(), None);
+        assert_eq!(iter.next_back(), None);
+    }
+
+    #[test]
+    fn into_iter() {
+        let mut tree = BTree::new();
+        for i in 0..100u8 {
+            tree.insert(i32::from(i), BigStruct { a: [i; 500] });
+        }
+
+        let mut iter = tree.clone().into_iter();
+        assert_eq!(iter.next(), Some((0, BigStruct { a: [0; 500] })));
+        assert_eq!(iter.next_back(), Some((99, BigStruct { a: [99; 500] })));
+        assert_eq!(iter.len(), 98);
+        assert!(tree.into_iter().rev().map(|(k, _)| k).eq((0..100).rev()));
+    }
+
+    #[test]
+    fn iter_empty() {
+        let tree = BTree::<i32, i32>::new();
+        assert!(tree.is_empty());
+        assert_eq!(tree.iter().next(), None);
+        assert_eq!(tree.into_iter().next_back(), None);
+    }
+
+    #[test]
+    fn rank_and_select() {
+        let mut tree = BTree::new();
+        for i in 0..5000 {
+            tree.insert((i * 7919) % 5000, i);
+        }
+        for i in (0..5000).step_by(2) {
+            tree.remove(&((i * 7919) % 5000), &i);
+        }
+
+        // Only odd multipliers are left, which map to odd keys.
+        assert_eq!(tree.len(), 2500);
+        for (index, key) in (1..5000).step_by(2).enumerate() {
+            assert_eq!(tree.rank(&key), index);
+            assert_eq!(tree.rank(&(key + 1)), index + 1);
+            assert_eq!(tree.rank_rev(&key), 2499 - index);
+            assert_eq!(tree.select(index).map(|(k, _)| *k), Some(key));
+        }
+        assert_eq!(tree.select(2500), None);
+    }
+
+    #[test]
+    fn rank_duplicates() {
+        let mut tree = BTree::new();
+        for i in 0..3000 {
+            tree.insert(i % 3, i);
+        }
+
+        assert_eq!(tree.rank(&0), 0);
+        assert_eq!(tree.rank(&1), 1000);
+        assert_eq!(tree.rank(&2), 2000);
+        assert_eq!(tree.rank_rev(&1), 1000);
+        assert_eq!(tree.rank_rev(&-1), 3000);
+        assert_eq!(tree.select(1999).map(|(k, _)| *k), Some(1));
+    }
+
+    #[test]
+    fn percentile() {
+        let mut tree = BTree::new();
+        for i in 1..=100u8 {
+            tree.insert(i32::from(i), BigStruct { a: [i; 500] });
+        }
+
+        assert_eq!(tree.percentile(90.0).map(|(k, _)| *k), Some(90));
+        assert_eq!(tree.percentile(0.0).map(|(k, _)| *k), Some(1));
+        assert_eq!(tree.percentile(100.0).map(|(k, _)| *k), Some(100));
+        assert_eq!(tree.percentile(50.5).map(|(k, _)| *k), Some(51));
+        assert_eq!(BTree::<i32, i32>::new().percentile(50.0), None);
+    }
+
+    #[test]
+    fn from_sorted_iter() {
+        for n in [0, 1, 2, 3, 4, 5, 7, 8, 9, 15, 16, 17, 63, 64, 65, 200u8] {
+            let entries = (0..n).map(|i| (i32::from(i), BigStruct { a: [i; 500] }));
+            let tree = BTree::from_sorted_iter(entries);
+
+            tree.validate().unwrap();
+            assert_eq!(tree.len(), usize::from(n));
+            assert!(tree.iter().map(|(k, _)| *k).eq(0..i32::from(n)));
+            assert!(tree
+                .iter()
+                .rev()
+                .map(|(k, _)| *k)
+                .eq((0..i32::from(n)).rev()));
+            for i in 0..n {
+                assert_eq!(tree.find(i32::from(i)), Some(&BigStruct { a: [i; 500] }));
+                assert_eq!(tree.rank(&i32::from(i)), usize::from(i));
+            }
+        }
+    }
+
+    #[test]
+    fn from_sorted_iter_then_modify() {
+        let mut tree = BTree::from_sorted_iter((0..10_000).map(|i| (i / 2, i)));
+        assert_eq!(tree.len(), 10_000);
+        assert_eq!(tree.get_greatest_n(2), vec![9999, 9998]);
+
+        for i in 0..5000 {
+            tree.insert(i / 2, -i);
+        }
+        for i in (0..10_000).step_by(2) {
+            assert_eq!(tree.remove(&(i / 2), &i), Some(i));
+        }
+
+        let mut keys = (0..5000)
+            .chain((0..5000).map(|i| i / 2))
+            .collect::<Vec<_>>();
+        keys.sort_unstable();
+        tree.validate().unwrap();
+        assert_eq!(tree.len(), 10_000);
+        assert!(tree.iter().map(|(k, _)| *k).eq(keys));
+    }
+
+    #[test]
+    fn validate_after_inserts() {
+        let mut tree = BTree::new();
+        tree.validate().unwrap();
+        for i in 0..1000 {
+            tree.insert((i * 7919) % 1000, Wide::new(i));
+            tree.validate().unwrap();
+        }
+    }
+
+    #[test]
+    fn validate_catches_broken_trees() {
+        let tree = BTree::from_sorted_iter((0..100).map(|i| (i, Wide::new(i))));
+        tree.validate().unwrap();
+
+        let mut unordered = tree.clone();
+        unordered.root.children[0].keys.swap(0, 1);
+        assert!(unordered.validate().is_err());
+
+        let mut underfull = tree.clone();
+        let leaf = underfull.root.children[0].children.last_mut().unwrap();
+        leaf.keys.truncate(0);
+        leaf.values.truncate(0);
+        assert!(underfull.validate().is_err());
+
+        let mut bad_size = tree;
+        bad_size.root.size += 1;
+        assert!(bad_size.validate().is_err());
+    }
+
+    #[test]
+    fn random_ops_small_order() {
+        for seed in 1..=4 {
+            random_ops(seed, 4000, 300, Wide::new);
+        }
+    }
+
+    #[test]
+    fn random_ops_page_order() {
+        random_ops(42, 30_000, 5000, |v| v);
+        random_ops(7, 30_000, 20, |v| v);
+    }
+
+    #[test]
+    fn multimap() {
+        let mut tree = BTree::new();
+        for i in 0..3000 {
+            tree.insert(i % 7, i);
+        }
+        tree.insert(3, 3);
+
+        assert_eq!(tree.count(3), 430);
+        assert_eq!(tree.count(7), 0);
+        let threes = tree.find_all(3);
+        assert_eq!(threes.len(), 430);
+        assert!(threes.iter().all(|v| **v % 7 == 3));
+        assert_eq!(tree.find_all(-1), Vec::<&i32>::new());
+
+        // `remove_one` takes out a single copy of a pair inserted twice.
+        assert_eq!(tree.remove_one(&3, &3), Some(3));
+        assert_eq!(tree.count(3), 429);
+        assert_eq!(tree.remove_one(&3, &3), Some(3));
+        assert_eq!(tree.remove_one(&3, &3), None);
+        assert_eq!(tree.find_all(3).len(), 428);
+        tree.validate().unwrap();
+    }
+
+    #[test]
+    fn multimap_remove_by_value() {
+        let mut tree = BTree::new();
+        for i in 0..500 {
+            tree.insert(i % 2, i);
+        }
+
+        // Only the pair with the given value goes, whichever node holds it.
+        for i in (0..500).step_by(4) {
+            assert_eq!(tree.remove_one(&0, &i), Some(i));
+            tree.validate().unwrap();
+        }
+        assert_eq!(tree.remove_one(&1, &2), None);
+        assert_eq!(tree.count(0), 125);
+        assert_eq!(tree.count(1), 250);
+        let zeros = tree.find_all(0).into_iter().copied();
+        assert!(zeros.eq((2..500).step_by(4)));
+        let ones = tree.find_all(1).into_iter().copied();
+        assert!(ones.eq((1..500).step_by(2)));
+    }
+
+    #[test]
+    fn multimap_insertion_order() {
+        let mut tree = BTree::new();
+        for i in 0..100 {
+            tree.insert(1, Wide::new(i));
+            tree.insert(0, Wide::new(-i));
+        }
+
+        let ones = tree.find_all(1);
+        assert!(ones.iter().map(|w| w.id).eq(0..100));
+        assert_eq!(tree.count(0), 100);
+    }
 }
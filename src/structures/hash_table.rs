@@ -1,5 +1,20 @@
+use std::hash::{BuildHasher, Hasher};
+use std::iter::{Chain, FusedIterator};
+use std::{mem, slice, vec};
+
 use anyhow::{anyhow, Result};
 
+use super::hasher::{self, Fnv1a};
+use super::map::Map;
+
+// Bucket count of tables created with `Default`.
+const DEFAULT_SIZE: usize = 17;
+// Average number of items per bucket that triggers a resize.
+const MAX_LOAD_FACTOR: usize = 1;
+// Buckets of the old table moved into the new one on every insertion while
+// resizing, so the cost of a resize is spread over many operations.
+const REHASH_STEP: usize = 8;
+
 #[derive(Clone, Debug)]
 struct TableCell<K, V> {
     item: Vec<(K, V)>,
@@ -11,95 +26,325 @@ impl<K, V> Default for TableCell<K, V> {
     }
 }
 
+/// Separate-chaining hash table. `S` picks the hash function, see
+/// [`super::hasher`].
 #[derive(Clone, Debug)]
-pub struct HashTable<K, V> {
+pub struct HashTable<K, V, S = Fnv1a> {
     items: Vec<TableCell<K, V>>,
     count: usize,
     size: usize,
+    // Buckets still waiting to be moved into `items` after a resize, and how
+    // many of them were already moved.
+    old_items: Vec<TableCell<K, V>>,
+    migrated: usize,
+    hasher: S,
 }
 
+// Position of an item: its bucket, in the current or the old table, and its
+// index inside the bucket.
+#[derive(Clone, Copy)]
+struct Slot {
+    old: bool,
+    bucket: usize,
+    index: usize,
+}
+
+/// Bucket usage of a [`HashTable`], returned by [`HashTable::stats`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct TableStats {
+    pub len: usize,
+    pub buckets: usize,
+    /// Items per bucket.
+    pub load_factor: f64,
+    pub empty_buckets: usize,
+    pub empty_ratio: f64,
+    pub max_chain: usize,
+    /// Mean chain length over the buckets that aren't empty, i.e. how many
+    /// keys a successful lookup compares against on average.
+    pub mean_chain: f64,
+    /// `histogram[n]` is the number of buckets holding exactly `n` items.
+    pub histogram: Vec<usize>,
+}
+
+/// A view into a single key of a [`HashTable`], returned by
+/// [`HashTable::entry`].
+pub enum Entry<'a, K, V, S = Fnv1a> {
+    Occupied(&'a mut V),
+    Vacant(&'a mut HashTable<K, V, S>, K),
+}
+
+/// Keys of a [`HashTable`]. Implementations feed the bytes that identify
+/// the key into `state`; the table's hasher decides how they are mixed.
+/// Structs and enums can use `#[derive(Hashable)]`.
 pub trait Hashable {
-    fn hash(&self) -> usize;
+    fn hash<H: Hasher>(&self, state: &mut H);
+}
+
+pub use hashable_derive::Hashable;
+
+impl Hashable for str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write(self.as_bytes());
+        // Ends the string, so ("ab", "c") and ("a", "bc") differ as keys.
+        state.write_u8(0xff);
+    }
 }
 
 impl Hashable for String {
-    fn hash(&self) -> usize {
-        let p = 31;
-        let mut acc = 0;
-        for (i, c) in self.chars().enumerate() {
-            acc += (p ^ i) * c as usize;
-        }
-        acc
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl<T: Hashable + ?Sized> Hashable for &T {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+macro_rules! impl_hashable_int {
+    ($($ty:ty => $write:ident),* $(,)?) => {
+        $(
+            impl Hashable for $ty {
+                fn hash<H: Hasher>(&self, state: &mut H) {
+                    state.$write(*self);
+                }
+            }
+        )*
+    };
+}
+
+impl_hashable_int!(
+    u8 => write_u8,
+    u16 => write_u16,
+    u32 => write_u32,
+    u64 => write_u64,
+    u128 => write_u128,
+    usize => write_usize,
+    i8 => write_i8,
+    i16 => write_i16,
+    i32 => write_i32,
+    i64 => write_i64,
+    i128 => write_i128,
+    isize => write_isize,
+);
+
+impl Hashable for bool {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u8(u8::from(*self));
     }
 }
 
-impl Hashable for u32 {
-    fn hash(&self) -> usize {
-        // 17s^2
-        17 * (*self ^ 2) as usize
+impl Hashable for char {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u32(u32::from(*self));
     }
 }
 
+macro_rules! impl_hashable_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: Hashable),+> Hashable for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                let ($($name,)+) = self;
+                $($name.hash(state);)+
+            }
+        }
+    };
+}
+
+impl_hashable_tuple!(A);
+impl_hashable_tuple!(A B);
+impl_hashable_tuple!(A B C);
+impl_hashable_tuple!(A B C D);
+
 #[allow(dead_code)]
 impl<K, V> HashTable<K, V>
 where
     K: Clone + Default + Hashable + PartialEq,
     V: Clone + Default,
 {
+    /// Creates a table with `size` buckets. The table grows by itself as
+    /// items are inserted, so `size` is only a hint.
     pub fn new(size: usize) -> Self {
+        Self::with_hasher(size, Fnv1a::default())
+    }
+}
+
+#[allow(dead_code)]
+impl<K, V, S> HashTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher,
+{
+    /// Like [`HashTable::new`], hashing keys with `hasher`.
+    pub fn with_hasher(size: usize, hasher: S) -> Self {
+        let size = size.max(1);
         HashTable {
             items: vec![TableCell::default(); size],
             count: 0,
             size,
+            old_items: Vec::new(),
+            migrated: 0,
+            hasher,
         }
     }
 
+    fn hash_key(&self, key: &K) -> usize {
+        hasher::hash_key(&self.hasher, key)
+    }
+
     pub fn rehash(&self, hash: usize) -> usize {
         hash % self.size
     }
 
-    pub fn insert(&mut self, key: &K, value: V) -> Result<()> {
-        let hash = self.rehash(key.hash());
-        if let Some(i) = self.items.get_mut(hash) {
-            i.item.push((key.clone(), value));
-        } else {
-            return Err(anyhow!("Error inserting item"));
+    /// Inserts `value` under `key`, replacing and returning the previous
+    /// value if the key was already present.
+    pub fn insert(&mut self, key: &K, value: V) -> Result<Option<V>> {
+        if let Some(slot) = self.find_slot(key) {
+            return Ok(Some(mem::replace(&mut self.slot_mut(slot).1, value)));
         }
-        self.count += 1;
-        Ok(())
+        self.push(key.clone(), value)?;
+        Ok(None)
     }
 
-    pub fn get(&self, key: &K) -> Option<V> {
-        let hash = self.rehash(key.hash());
-        if let Some(i) = self.items.get(hash) {
-            for a in &i.item {
-                if a.0 == *key {
-                    return Some(a.1.clone());
-                }
-            }
+    // Adds an item whose key is known not to be in the table.
+    fn push(&mut self, key: K, value: V) -> Result<Slot> {
+        self.migrate(REHASH_STEP);
+        let hash = self.rehash(self.hash_key(&key));
+        let Some(i) = self.items.get_mut(hash) else {
+            return Err(anyhow!("Error inserting item"));
+        };
+        i.item.push((key, value));
+        let mut slot = Slot {
+            old: false,
+            bucket: hash,
+            index: i.item.len() - 1,
+        };
+        self.count += 1;
+        if self.count > self.size * MAX_LOAD_FACTOR {
+            // The item is pushed last into its bucket and nothing else moves
+            // into the old table, so it is easy to find again.
+            self.resize(self.size * 2 + 1);
+            slot.old = true;
         }
-        None
+        Ok(slot)
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        self.find_slot(key).map(|slot| &self.slot(slot).1)
     }
 
     pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
-        let hash = self.rehash(key.hash());
-        if let Some(i) = self.items.get_mut(hash) {
-            i.item.iter_mut().find(|a| a.0 == *key).map(|a| &mut a.1)
+        self.find_slot(key).map(|slot| &mut self.slot_mut(slot).1)
+    }
+
+    /// Removes `key` from the table, returning its value if it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.migrate(REHASH_STEP);
+        let slot = self.find_slot(key)?;
+        self.count -= 1;
+        Some(self.cell_mut(slot).item.swap_remove(slot.index).1)
+    }
+
+    /// Gets the entry of `key`, to read or update it in place with a single
+    /// lookup.
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, S> {
+        match self.find_slot(&key) {
+            Some(slot) => Entry::Occupied(&mut self.slot_mut(slot).1),
+            None => Entry::Vacant(self, key),
+        }
+    }
+
+    fn find_slot(&self, key: &K) -> Option<Slot> {
+        let hash = self.hash_key(key);
+        let new = (false, self.rehash(hash));
+        let old = (!self.old_items.is_empty()).then(|| (true, hash % self.old_items.len()));
+        Some(new).into_iter().chain(old).find_map(|(old, bucket)| {
+            let slot = Slot {
+                old,
+                bucket,
+                index: 0,
+            };
+            self.cell(slot)
+                .item
+                .iter()
+                .position(|a| a.0 == *key)
+                .map(|index| Slot { index, ..slot })
+        })
+    }
+
+    fn cell(&self, slot: Slot) -> &TableCell<K, V> {
+        if slot.old {
+            &self.old_items[slot.bucket]
         } else {
-            None
+            &self.items[slot.bucket]
         }
     }
 
-    pub fn get_mut_or_default(&mut self, key: &K) -> Result<&mut V> {
-        if self.get(key).is_some() {
-            self.get_mut(key).ok_or_else(|| anyhow!("Error getting item"))
+    fn cell_mut(&mut self, slot: Slot) -> &mut TableCell<K, V> {
+        if slot.old {
+            &mut self.old_items[slot.bucket]
         } else {
-            self.insert(key, Default::default())?;
-            self.get_mut(key)
-                .ok_or_else(|| anyhow!("Could not get mut or default"))
+            &mut self.items[slot.bucket]
+        }
+    }
+
+    fn slot(&self, slot: Slot) -> &(K, V) {
+        &self.cell(slot).item[slot.index]
+    }
+
+    fn slot_mut(&mut self, slot: Slot) -> &mut (K, V) {
+        &mut self.cell_mut(slot).item[slot.index]
+    }
+
+    /// Starts moving every item into a table with `size` buckets. The items
+    /// are moved a few buckets at a time by the following insertions.
+    fn resize(&mut self, size: usize) {
+        // Finish any resize still in progress first.
+        self.migrate(usize::MAX);
+        self.old_items = mem::replace(&mut self.items, vec![TableCell::default(); size.max(1)]);
+        self.size = size.max(1);
+        self.migrated = 0;
+    }
+
+    /// Moves up to `steps` buckets of a pending resize into the new table.
+    fn migrate(&mut self, steps: usize) {
+        if self.old_items.is_empty() {
+            return;
+        }
+        let end = self
+            .migrated
+            .saturating_add(steps)
+            .min(self.old_items.len());
+        for i in self.migrated..end {
+            for (key, value) in mem::take(&mut self.old_items[i].item) {
+                let hash = self.rehash(self.hash_key(&key));
+                self.items[hash].item.push((key, value));
+            }
+        }
+        self.migrated = end;
+        if self.migrated == self.old_items.len() {
+            self.old_items = Vec::new();
+            self.migrated = 0;
+        }
+    }
+
+    /// Shrinks the table to roughly one bucket per item. Like growing, the
+    /// items are moved incrementally by the following insertions.
+    pub fn shrink_to_fit(&mut self) {
+        let size = self.count.div_ceil(MAX_LOAD_FACTOR).max(1);
+        if size < self.size {
+            self.resize(size);
         }
     }
 
+    pub fn get_mut_or_default(&mut self, key: &K) -> Result<&mut V> {
+        self.entry(key.clone()).or_try_insert_with(V::default)
+    }
+
     pub fn contains_key(&self, key: &K) -> bool {
         self.get(key).is_some()
     }
@@ -108,18 +353,425 @@ where
     where
         F: FnMut(&K, &V),
     {
-        for i in &self.items {
-            for a in &i.item {
-                f(&a.0, &a.1);
+        for (k, v) in self {
+            f(k, v);
+        }
+    }
+}
+
+impl<K, V, S> HashTable<K, V, S> {
+    /// Number of items stored in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Reports how the items are spread over the buckets. Buckets of a
+    /// resize in progress that weren't migrated yet are counted too.
+    #[must_use]
+    pub fn stats(&self) -> TableStats {
+        let cells = self
+            .items
+            .iter()
+            .chain(self.old_items.iter().skip(self.migrated));
+        let mut histogram = Vec::new();
+        let mut buckets = 0;
+        for cell in cells {
+            let chain = cell.item.len();
+            if histogram.len() <= chain {
+                histogram.resize(chain + 1, 0);
+            }
+            histogram[chain] += 1;
+            buckets += 1;
+        }
+
+        let empty_buckets = histogram.first().copied().unwrap_or(0);
+        let used_buckets = buckets - empty_buckets;
+        TableStats {
+            len: self.count,
+            buckets,
+            load_factor: self.count as f64 / buckets as f64,
+            empty_buckets,
+            empty_ratio: empty_buckets as f64 / buckets as f64,
+            max_chain: histogram.len().saturating_sub(1),
+            mean_chain: if used_buckets == 0 {
+                0.0
+            } else {
+                self.count as f64 / used_buckets as f64
+            },
+            histogram,
+        }
+    }
+
+    /// Iterates over the items in no particular order.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            cells: self.items.iter().chain(&self.old_items),
+            items: [].iter(),
+            remaining: self.count,
+        }
+    }
+
+    /// Iterates over the items in no particular order, with mutable access
+    /// to the values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, K, V> {
+        IterMut {
+            cells: self.items.iter_mut().chain(&mut self.old_items),
+            items: [].iter_mut(),
+            remaining: self.count,
+        }
+    }
+
+    #[must_use]
+    pub fn keys(&self) -> Keys<'_, K, V> {
+        Keys(self.iter())
+    }
+
+    #[must_use]
+    pub fn values(&self) -> Values<'_, K, V> {
+        Values(self.iter())
+    }
+
+    /// Removes every item from the table, returning them in no particular
+    /// order. The bucket count is kept, so the table can be refilled without
+    /// growing again.
+    pub fn drain(&mut self) -> IntoIter<K, V> {
+        let size = self.size;
+        let items = mem::replace(
+            &mut self.items,
+            (0..size).map(|_| TableCell::default()).collect(),
+        );
+        let old_items = mem::take(&mut self.old_items);
+        let remaining = mem::take(&mut self.count);
+        self.migrated = 0;
+        IntoIter {
+            cells: items.into_iter().chain(old_items),
+            items: Vec::new().into_iter(),
+            remaining,
+        }
+    }
+}
+
+// Buckets of the current table followed by the ones still waiting to be
+// migrated.
+type Cells<I> = Chain<I, I>;
+
+/// Borrowing iterator over a [`HashTable`], created by [`HashTable::iter`].
+pub struct Iter<'a, K, V> {
+    cells: Cells<slice::Iter<'a, TableCell<K, V>>>,
+    items: slice::Iter<'a, (K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.items.next() {
+                self.remaining -= 1;
+                return Some((k, v));
+            }
+            self.items = self.cells.next()?.item.iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+/// Mutable iterator over a [`HashTable`], created by [`HashTable::iter_mut`].
+pub struct IterMut<'a, K, V> {
+    cells: Cells<slice::IterMut<'a, TableCell<K, V>>>,
+    items: slice::IterMut<'a, (K, V)>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for IterMut<'a, K, V> {
+    type Item = (&'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((k, v)) = self.items.next() {
+                self.remaining -= 1;
+                return Some((&*k, v));
+            }
+            self.items = self.cells.next()?.item.iter_mut();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for IterMut<'_, K, V> {}
+
+impl<K, V> FusedIterator for IterMut<'_, K, V> {}
+
+/// Owning iterator over a [`HashTable`], created by `into_iter` and
+/// [`HashTable::drain`].
+pub struct IntoIter<K, V> {
+    cells: Cells<vec::IntoIter<TableCell<K, V>>>,
+    items: vec::IntoIter<(K, V)>,
+    remaining: usize,
+}
+
+impl<K, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                self.remaining -= 1;
+                return Some(item);
             }
+            self.items = self.cells.next()?.item.into_iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> Default for IntoIter<K, V> {
+    fn default() -> Self {
+        IntoIter {
+            cells: Vec::new().into_iter().chain(Vec::new()),
+            items: Vec::new().into_iter(),
+            remaining: 0,
+        }
+    }
+}
+
+impl<K, V> ExactSizeIterator for IntoIter<K, V> {}
+
+impl<K, V> FusedIterator for IntoIter<K, V> {}
+
+/// Iterator over the keys of a [`HashTable`], created by [`HashTable::keys`].
+pub struct Keys<'a, K, V>(Iter<'a, K, V>);
+
+impl<'a, K, V> Iterator for Keys<'a, K, V> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(k, _)| k)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Keys<'_, K, V> {}
+
+impl<K, V> FusedIterator for Keys<'_, K, V> {}
+
+/// Iterator over the values of a [`HashTable`], created by
+/// [`HashTable::values`].
+pub struct Values<'a, K, V>(Iter<'a, K, V>);
+
+impl<'a, K, V> Iterator for Values<'a, K, V> {
+    type Item = &'a V;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(_, v)| v)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.0.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for Values<'_, K, V> {}
+
+impl<K, V> FusedIterator for Values<'_, K, V> {}
+
+impl<K, V, S> IntoIterator for HashTable<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        IntoIter {
+            cells: self.items.into_iter().chain(self.old_items),
+            items: Vec::new().into_iter(),
+            remaining: self.count,
         }
     }
 }
 
-impl<U, T> Extend<(U, T)> for HashTable<U, T>
+impl<'a, K, V, S> IntoIterator for &'a HashTable<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, K, V, S> IntoIterator for &'a mut HashTable<K, V, S> {
+    type Item = (&'a K, &'a mut V);
+    type IntoIter = IterMut<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<'a, K, V, S> Entry<'a, K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher,
+{
+    /// Returns the value of the entry, inserting the result of `f` first if
+    /// the key is not in the table.
+    ///
+    /// # Errors
+    /// Fails if the value can't be inserted.
+    pub fn or_try_insert_with<F>(self, f: F) -> Result<&'a mut V>
+    where
+        F: FnOnce() -> V,
+    {
+        match self {
+            Entry::Occupied(value) => Ok(value),
+            Entry::Vacant(table, key) => {
+                let slot = table.push(key, f())?;
+                Ok(&mut table.slot_mut(slot).1)
+            }
+        }
+    }
+
+    /// Like [`Entry::or_try_insert_with`], for callers that treat a failed
+    /// insertion as a bug.
+    ///
+    /// # Panics
+    /// Panics if the value can't be inserted.
+    pub fn or_insert_with<F>(self, f: F) -> &'a mut V
+    where
+        F: FnOnce() -> V,
+    {
+        self.or_try_insert_with(f).expect("Error inserting item")
+    }
+
+    /// # Panics
+    /// Panics if the value can't be inserted.
+    pub fn or_insert(self, value: V) -> &'a mut V {
+        self.or_insert_with(|| value)
+    }
+
+    /// # Panics
+    /// Panics if the value can't be inserted.
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+
+    /// Runs `f` on the value if the key is already in the table.
+    #[must_use]
+    pub fn and_modify<F>(self, f: F) -> Self
+    where
+        F: FnOnce(&mut V),
+    {
+        match self {
+            Entry::Occupied(value) => {
+                f(value);
+                Entry::Occupied(value)
+            }
+            vacant @ Entry::Vacant(..) => vacant,
+        }
+    }
+}
+
+impl<K, V, S> Default for HashTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(DEFAULT_SIZE, S::default())
+    }
+}
+
+impl<K, V, S> Map<K, V> for HashTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher + Default,
+{
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        HashTable::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        HashTable::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        HashTable::insert(self, &key, value)
+    }
+
+    fn get_or_try_insert_with<F>(&mut self, key: K, f: F) -> Result<&mut V>
+    where
+        F: FnOnce() -> V,
+    {
+        self.entry(key).or_try_insert_with(f)
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        HashTable::remove(self, key)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        HashTable::iter(self)
+    }
+
+    fn stats(&self) -> TableStats {
+        HashTable::stats(self)
+    }
+}
+
+impl<K, V, S> FromIterator<(K, V)> for HashTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher + Default,
+{
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut table = HashTable::default();
+        table.extend(iter);
+        table
+    }
+}
+
+impl<U, T, S> Extend<(U, T)> for HashTable<U, T, S>
 where
     U: Clone + Default + Hashable + PartialEq,
     T: Clone + Default,
+    S: BuildHasher,
 {
     fn extend<I: IntoIterator<Item = (U, T)>>(&mut self, iter: I) {
         for (k, v) in iter {
@@ -131,6 +783,7 @@ where
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::structures::hasher::{Polynomial, SipHash};
 
     #[test]
     fn insert() {
@@ -157,7 +810,300 @@ mod test {
 
         assert_eq!(
             table.get(&"Peter Parker".to_string()),
-            Some("SpiderMan".to_string())
+            Some(&"SpiderMan".to_string())
         );
     }
+
+    #[test]
+    fn grows_while_inserting() {
+        let mut table = HashTable::new(1);
+        for i in 0..10_000u32 {
+            table.insert(&i, i * 2).unwrap();
+            // Items must stay reachable in the middle of a resize.
+            assert_eq!(table.get(&(i / 2)), Some(&(i / 2 * 2)));
+        }
+
+        assert!(table.size >= 10_000 / MAX_LOAD_FACTOR);
+        for i in 0..10_000u32 {
+            assert_eq!(table.get(&i), Some(&(i * 2)));
+        }
+        *table.get_mut(&7).unwrap() = 0;
+        assert_eq!(table.get(&7), Some(&0));
+
+        let mut count = 0;
+        table.for_each(|_, _| count += 1);
+        assert_eq!(count, 10_000);
+    }
+
+    #[test]
+    fn get_mut_during_resize() {
+        let mut table = HashTable::new(4);
+        for i in 0..5u32 {
+            table.insert(&i, i).unwrap();
+        }
+        assert!(!table.old_items.is_empty());
+
+        for i in 0..5u32 {
+            *table.get_mut(&i).unwrap() += 10;
+        }
+        for i in 0..5u32 {
+            assert_eq!(table.get(&i), Some(&(i + 10)));
+        }
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut table = HashTable::new(1000);
+        for i in 0..10u32 {
+            table.insert(&i, i).unwrap();
+        }
+        table.shrink_to_fit();
+
+        assert_eq!(table.size, 10);
+        for i in 10..20u32 {
+            table.insert(&i, i).unwrap();
+        }
+        assert!(table.old_items.is_empty());
+        for i in 0..20u32 {
+            assert_eq!(table.get(&i), Some(&i));
+        }
+    }
+
+    #[test]
+    fn insert_replaces() {
+        let mut table = HashTable::new(4);
+        let key = "Peter Parker".to_string();
+        assert_eq!(table.insert(&key, 1).unwrap(), None);
+        assert_eq!(table.insert(&key, 2).unwrap(), Some(1));
+
+        assert_eq!(table.get(&key), Some(&2));
+        assert_eq!(table.count, 1);
+    }
+
+    #[test]
+    fn remove() {
+        let mut table = HashTable::new(1);
+        for i in 0..100u32 {
+            table.insert(&i, i).unwrap();
+        }
+        for i in (0..100u32).step_by(2) {
+            assert_eq!(table.remove(&i), Some(i));
+            assert_eq!(table.remove(&i), None);
+        }
+
+        assert_eq!(table.count, 50);
+        for i in 0..100u32 {
+            assert_eq!(table.get(&i), (i % 2 == 1).then_some(&i));
+        }
+    }
+
+    #[test]
+    fn entry() {
+        let mut table: HashTable<String, Vec<u32>> = HashTable::new(1);
+        for i in 0..50u32 {
+            // Vacant entries may trigger a resize while being inserted.
+            table.entry(format!("tag{}", i % 10)).or_default().push(i);
+        }
+        assert_eq!(table.count, 10);
+        assert_eq!(
+            table.get(&"tag3".to_string()),
+            Some(&vec![3, 13, 23, 33, 43])
+        );
+
+        for i in 0..20u32 {
+            let count = table
+                .entry(i.to_string())
+                .and_modify(|v| v.push(0))
+                .or_insert_with(|| vec![i]);
+            assert_eq!(count, &vec![i]);
+            assert_eq!(table.get(&i.to_string()), Some(&vec![i]));
+        }
+        let value = table
+            .entry("0".to_string())
+            .and_modify(|v| v.push(1))
+            .or_insert(vec![]);
+        assert_eq!(value, &vec![0, 1]);
+    }
+
+    #[test]
+    fn iterators() {
+        // Starting small keeps a resize in progress while iterating.
+        let mut table = HashTable::new(1);
+        for i in 0..70u32 {
+            table.insert(&i, i * 10).unwrap();
+        }
+        assert!(!table.old_items.is_empty());
+        assert_eq!(table.len(), 70);
+        assert_eq!(table.iter().len(), 70);
+
+        let mut keys = table.keys().copied().collect::<Vec<_>>();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..70).collect::<Vec<_>>());
+        assert_eq!(table.values().sum::<u32>(), 24_150);
+        assert!(table.iter().all(|(k, v)| *v == k * 10));
+
+        for (k, v) in &mut table {
+            *v += k;
+        }
+        assert_eq!(table.get(&7), Some(&77));
+
+        let mut items = table.into_iter().collect::<Vec<_>>();
+        items.sort_unstable();
+        assert_eq!(items, (0..70).map(|i| (i, i * 11)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn drain_and_collect() {
+        let mut table = (0..50u32)
+            .map(|i| (i.to_string(), i))
+            .collect::<HashTable<_, _>>();
+        assert_eq!(table.len(), 50);
+        assert_eq!(table.get(&"42".to_string()), Some(&42));
+
+        let size = table.size;
+        let drained = table.drain();
+        assert_eq!(drained.len(), 50);
+        assert_eq!(drained.map(|(_, v)| v).sum::<u32>(), 1225);
+        assert!(table.is_empty());
+        assert_eq!(table.iter().next(), None);
+        assert_eq!(table.get(&"42".to_string()), None);
+
+        table.insert(&"42".to_string(), 0).unwrap();
+        assert_eq!(table.size, size);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn hashers() {
+        fn check<S: BuildHasher>(mut table: HashTable<String, u32, S>) {
+            let names = ["ab", "ba", "Lionel Messi", "Messi Lionel", ""];
+            for (i, name) in (0..).zip(names) {
+                table.insert(&name.to_string(), i).unwrap();
+            }
+            assert_eq!(table.get(&"ba".to_string()), Some(&1));
+            assert_eq!(table.get(&"Messi Lionel".to_string()), Some(&3));
+            assert_eq!(table.get(&String::new()), Some(&4));
+            assert_eq!(table.get(&"Messi".to_string()), None);
+        }
+
+        check(HashTable::new(1));
+        check(HashTable::with_hasher(1, SipHash::new()));
+        check(HashTable::with_hasher(1, Polynomial::default()));
+        check(HashTable::<_, _, Fnv1a>::default());
+    }
+
+    #[test]
+    fn stats() {
+        let mut table = HashTable::new(8);
+        let stats = table.stats();
+        assert_eq!(stats.len, 0);
+        assert_eq!(stats.buckets, 8);
+        assert_eq!(stats.empty_buckets, 8);
+        assert_eq!(stats.max_chain, 0);
+        assert!(stats.mean_chain.abs() < f64::EPSILON);
+        assert_eq!(stats.histogram, vec![8]);
+
+        for i in 0..8u32 {
+            table.insert(&i, i).unwrap();
+        }
+        let stats = table.stats();
+        assert_eq!(stats.len, 8);
+        assert_eq!(stats.buckets, 8);
+        assert!((stats.load_factor - 1.0).abs() < f64::EPSILON);
+        assert_eq!(stats.histogram.iter().sum::<usize>(), 8);
+        let chained = (0..).zip(&stats.histogram).map(|(n, b)| n * b);
+        assert_eq!(chained.sum::<usize>(), 8);
+        assert_eq!(stats.max_chain, stats.histogram.len() - 1);
+        let empty_ratio = stats.empty_buckets as f64 / stats.buckets as f64;
+        assert!((stats.empty_ratio - empty_ratio).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn stats_during_resize() {
+        let mut table = HashTable::new(4);
+        for i in 0..5u32 {
+            table.insert(&i, i).unwrap();
+        }
+        assert!(!table.old_items.is_empty());
+
+        let stats = table.stats();
+        assert_eq!(stats.len, 5);
+        assert_eq!(stats.histogram.iter().sum::<usize>(), stats.buckets);
+        let chained = (0..).zip(&stats.histogram).map(|(n, b)| n * b);
+        assert_eq!(chained.sum::<usize>(), 5);
+    }
+
+    #[derive(Clone, Default, PartialEq, Debug, Hashable)]
+    struct RatingKey {
+        user_id: u32,
+        sofifa_id: u32,
+    }
+
+    #[derive(Clone, Default, PartialEq, Debug, Hashable)]
+    enum PlayerKey {
+        #[default]
+        Unknown,
+        Id(u32),
+        Name {
+            first: String,
+            last: String,
+        },
+    }
+
+    fn fnv<K: Hashable>(key: &K) -> usize {
+        hasher::hash_key(&Fnv1a::default(), key)
+    }
+
+    #[test]
+    fn derived_keys() {
+        let mut table = HashTable::new(1);
+        for user_id in 0..20u32 {
+            for sofifa_id in 0..20u32 {
+                let key = RatingKey { user_id, sofifa_id };
+                table.insert(&key, user_id * 100 + sofifa_id).unwrap();
+            }
+        }
+        let key = RatingKey {
+            user_id: 7,
+            sofifa_id: 3,
+        };
+        assert_eq!(table.get(&key), Some(&703));
+        assert_eq!(fnv(&key), fnv(&(7u32, 3u32)));
+
+        let mut table = HashTable::new(1);
+        let name = PlayerKey::Name {
+            first: "Lionel".to_string(),
+            last: "Messi".to_string(),
+        };
+        table.insert(&PlayerKey::Unknown, 0).unwrap();
+        table.insert(&PlayerKey::Id(158_023), 1).unwrap();
+        table.insert(&name, 2).unwrap();
+        assert_eq!(table.get(&PlayerKey::Id(158_023)), Some(&1));
+        assert_eq!(table.get(&name), Some(&2));
+        assert_eq!(table.get(&PlayerKey::Id(0)), None);
+        assert_ne!(fnv(&PlayerKey::Unknown), fnv(&PlayerKey::Id(0)));
+    }
+
+    #[test]
+    fn builtin_keys() {
+        assert_ne!(fnv(&("ab", "c")), fnv(&("a", "bc")));
+        assert_eq!(fnv(&"Messi"), fnv(&"Messi".to_string()));
+        assert_ne!(fnv(&'a'), fnv(&'b'));
+        assert_ne!(fnv(&(1u8, 2i64, true)), fnv(&(2u8, 1i64, true)));
+
+        let mut table = HashTable::new(1);
+        for (i, c) in (0..).zip('a'..='z') {
+            table.insert(&(c, "letra"), i).unwrap();
+        }
+        assert_eq!(table.get(&('q', "letra")), Some(&16));
+        assert_eq!(table.get(&('q', "outra")), None);
+    }
+
+    #[test]
+    fn default_table() {
+        let mut table: HashTable<String, i32> = HashTable::default();
+        table.insert(&"key".to_string(), 1).unwrap();
+        assert_eq!(table.get(&"key".to_string()), Some(&1));
+        assert_eq!(table.size, DEFAULT_SIZE);
+    }
 }
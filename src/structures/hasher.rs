@@ -0,0 +1,116 @@
+//! Hashing strategies for [`HashTable`](super::hash_table::HashTable).
+//!
+//! Keys feed their bytes into a [`Hasher`] through
+//! [`Hashable`](super::hash_table::Hashable), and each table picks how those
+//! bytes are mixed with a [`BuildHasher`]:
+//!
+//! - [`Fnv1a`]: fast and deterministic, the default.
+//! - [`SipHash`]: seeded per table, so crafted keys can't force collisions.
+//! - [`Polynomial`]: the classic rolling hash `h * 31 + b`.
+
+use std::hash::{BuildHasher, BuildHasherDefault, Hasher};
+
+use super::hash_table::Hashable;
+
+/// Seeded `SipHash` 1-3, the same hasher `std::collections::HashMap` uses.
+pub type SipHash = std::collections::hash_map::RandomState;
+
+/// Builds [`Fnv1aHasher`]s.
+pub type Fnv1a = BuildHasherDefault<Fnv1aHasher>;
+
+/// Builds [`PolynomialHasher`]s.
+pub type Polynomial = BuildHasherDefault<PolynomialHasher>;
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+
+/// 64-bit FNV-1a.
+#[derive(Clone, Copy, Debug)]
+pub struct Fnv1aHasher(u64);
+
+impl Default for Fnv1aHasher {
+    fn default() -> Self {
+        Fnv1aHasher(FNV_OFFSET_BASIS)
+    }
+}
+
+impl Hasher for Fnv1aHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ u64::from(*b)).wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+const POLYNOMIAL_BASE: u64 = 31;
+
+/// Polynomial rolling hash over the bytes, modulo 2^64.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PolynomialHasher(u64);
+
+impl Hasher for PolynomialHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = self
+                .0
+                .wrapping_mul(POLYNOMIAL_BASE)
+                .wrapping_add(u64::from(*b));
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Hashes `key` with a fresh hasher from `builder`.
+// Only the low bits matter once the hash is reduced to a bucket, so
+// truncating on 32-bit targets is fine.
+#[allow(clippy::cast_possible_truncation)]
+pub(crate) fn hash_key<K: Hashable, S: BuildHasher>(builder: &S, key: &K) -> usize {
+    let mut state = builder.build_hasher();
+    key.hash(&mut state);
+    state.finish() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash_bytes<S: BuildHasher>(builder: &S, bytes: &[u8]) -> u64 {
+        let mut hasher = builder.build_hasher();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    #[test]
+    fn fnv1a_reference_values() {
+        assert_eq!(hash_bytes(&Fnv1a::default(), b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash_bytes(&Fnv1a::default(), b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(
+            hash_bytes(&Fnv1a::default(), b"foobar"),
+            0x8594_4171_f739_67e8
+        );
+    }
+
+    #[test]
+    fn polynomial() {
+        assert_eq!(hash_bytes(&Polynomial::default(), b"ab"), 97 * 31 + 98);
+        assert_ne!(
+            hash_bytes(&Polynomial::default(), b"ab"),
+            hash_bytes(&Polynomial::default(), b"ba")
+        );
+    }
+
+    #[test]
+    fn siphash_is_seeded() {
+        let a = SipHash::new();
+        let b = SipHash::new();
+        assert_eq!(hash_bytes(&a, b"Messi"), hash_bytes(&a, b"Messi"));
+        assert_ne!(hash_bytes(&a, b"Messi"), hash_bytes(&b, b"Messi"));
+    }
+}
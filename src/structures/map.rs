@@ -0,0 +1,46 @@
+use anyhow::Result;
+
+use super::hash_table::TableStats;
+
+/// Operations shared by the hash tables, so the database can be built on
+/// either the chained [`HashTable`](super::hash_table::HashTable) or the
+/// open-addressing [`OpenTable`](super::open_table::OpenTable).
+pub trait Map<K, V>: Default {
+    type Iter<'a>: Iterator<Item = (&'a K, &'a V)>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, key: &K) -> Option<&V>;
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+
+    /// Inserts `value` under `key`, returning the value it replaced.
+    ///
+    /// # Errors
+    /// Fails if the value can't be stored.
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>>;
+
+    /// Returns the value of `key`, inserting the result of `f` first if the
+    /// key is missing, with a single lookup.
+    ///
+    /// # Errors
+    /// Fails if the value can't be stored.
+    fn get_or_try_insert_with<F>(&mut self, key: K, f: F) -> Result<&mut V>
+    where
+        F: FnOnce() -> V;
+
+    fn remove(&mut self, key: &K) -> Option<V>;
+
+    /// Iterates over the items in no particular order.
+    fn iter(&self) -> Self::Iter<'_>;
+
+    fn stats(&self) -> TableStats;
+}
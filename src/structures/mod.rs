@@ -1,4 +1,10 @@
 pub mod btree;
 pub mod hash_table;
+pub mod hasher;
+pub mod map;
 pub mod multi_tst;
+pub mod open_table;
+pub mod ord_float;
+pub mod paged_btree;
+pub mod sharded_table;
 pub mod tst;
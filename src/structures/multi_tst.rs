@@ -41,6 +41,51 @@ where
             .collect()
     }
 
+    /// Values under keys at most `max` edits away from `key`, each with the
+    /// distance of its key.
+    #[must_use]
+    pub fn find_similar(&self, key: String, max: usize) -> Vec<(T, usize)> {
+        self.tst
+            .find_similar(key, max)
+            .into_iter()
+            .flat_map(|(_, values, distance)| values.into_iter().map(move |v| (v, distance)))
+            .collect()
+    }
+
+    /// Values under keys matching the glob `pattern`, see [`Tst::find_glob`].
+    ///
+    /// # Errors
+    /// Fails if `pattern` isn't a valid glob.
+    pub fn find_glob(&self, pattern: String) -> Result<Vec<T>> {
+        Ok(self
+            .tst
+            .find_glob(pattern)?
+            .into_iter()
+            .flat_map(|(_, values)| values)
+            .collect())
+    }
+
+    /// Raises the weight of `key` to `weight`, if lower, so a key ranks by
+    /// its heaviest value. Returns false if `key` isn't in the tree.
+    pub fn raise_weight(&mut self, key: String, weight: u64) -> bool {
+        match self.tst.get_weight(key.clone()) {
+            Some(current) if current < weight => self.tst.set_weight(key, weight),
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    /// Values of the `k` heaviest keys starting with `prefix`, heaviest
+    /// first, with the weight of their key.
+    #[must_use]
+    pub fn find_top(&self, prefix: String, k: usize) -> Vec<(Vec<T>, u64)> {
+        self.tst
+            .find_top(prefix, k)
+            .into_iter()
+            .map(|(_, values, weight)| (values, weight))
+            .collect()
+    }
+
     pub fn debug(&self) {
         self.tst.print_vertical();
     }
@@ -100,4 +145,57 @@ mod tests {
         assert_eq!(tst.find("ya".to_string()).len(), 1);
         assert_eq!(tst.find("y".to_string()).len(), 2);
     }
+
+    #[test]
+    fn accents_share_a_key() {
+        let mut tst = MultiTst::new();
+        tst.insert("Sergio Agüero".to_string(), 1).unwrap();
+        tst.insert("Sergio Aguero".to_string(), 2).unwrap();
+
+        assert_eq!(tst.get("sergio agüero".to_string()), Some(vec![1, 2]));
+        assert_eq!(tst.find("SERGIO AGU".to_string()), vec![1, 2]);
+    }
+
+    #[test]
+    fn find_similar() {
+        let mut tst = MultiTst::new();
+        tst.insert("messi".to_string(), 1).unwrap();
+        tst.insert("messi".to_string(), 2).unwrap();
+        tst.insert("mesut".to_string(), 3).unwrap();
+
+        assert_eq!(
+            tst.find_similar("mesi".to_string(), 1),
+            vec![(1, 1), (2, 1)]
+        );
+        assert_eq!(tst.find_similar("mesu".to_string(), 1), vec![(3, 1)]);
+    }
+
+    #[test]
+    fn find_glob() {
+        let mut tst = MultiTst::new();
+        tst.insert("robinho".to_string(), 1).unwrap();
+        tst.insert("robinho".to_string(), 2).unwrap();
+        tst.insert("robin".to_string(), 3).unwrap();
+
+        assert_eq!(tst.find_glob("*inho".to_string()).unwrap(), vec![1, 2]);
+        assert!(tst.find_glob("[rob".to_string()).is_err());
+    }
+
+    #[test]
+    fn find_top() {
+        let mut tst = MultiTst::new();
+        tst.insert("silva".to_string(), 1).unwrap();
+        tst.insert("silva".to_string(), 2).unwrap();
+        tst.insert("silvestre".to_string(), 3).unwrap();
+
+        assert!(tst.raise_weight("silva".to_string(), 10));
+        assert!(tst.raise_weight("silva".to_string(), 5));
+        assert!(tst.raise_weight("silvestre".to_string(), 7));
+        assert!(!tst.raise_weight("sil".to_string(), 7));
+
+        assert_eq!(
+            tst.find_top("sil".to_string(), 2),
+            vec![(vec![1, 2], 10), (vec![3], 7)]
+        );
+    }
 }
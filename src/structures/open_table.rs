@@ -0,0 +1,470 @@
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+use std::{mem, slice};
+
+use anyhow::Result;
+
+use super::hash_table::{Hashable, TableStats};
+use super::hasher::{self, Fnv1a};
+use super::map::Map;
+
+// Slot count of tables created with `Default`. Always a power of two.
+const DEFAULT_CAPACITY: usize = 16;
+// The table grows once more than MAX_LOAD_NUM / MAX_LOAD_DEN of the slots
+// are taken. Robin Hood hashing keeps probes short even at high loads.
+const MAX_LOAD_NUM: usize = 7;
+const MAX_LOAD_DEN: usize = 8;
+
+#[derive(Clone, Debug)]
+struct Bucket<K, V> {
+    hash: usize,
+    key: K,
+    value: V,
+}
+
+/// Open-addressing hash table with linear probing and Robin Hood
+/// displacement: an item being inserted takes the slot of any item that is
+/// closer to its ideal slot, so every probe sequence stays short. Removal
+/// shifts the following items back instead of leaving tombstones.
+#[derive(Clone, Debug)]
+pub struct OpenTable<K, V, S = Fnv1a> {
+    slots: Vec<Option<Bucket<K, V>>>,
+    count: usize,
+    hasher: S,
+}
+
+impl<K, V> OpenTable<K, V>
+where
+    K: Hashable + PartialEq,
+{
+    /// Creates a table with room for at least `capacity` slots.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self::with_hasher(capacity, Fnv1a::default())
+    }
+}
+
+impl<K, V, S> OpenTable<K, V, S>
+where
+    K: Hashable + PartialEq,
+    S: BuildHasher,
+{
+    /// Like [`OpenTable::new`], hashing keys with `hasher`.
+    pub fn with_hasher(capacity: usize, hasher: S) -> Self {
+        let capacity = capacity.max(DEFAULT_CAPACITY).next_power_of_two();
+        OpenTable {
+            slots: (0..capacity).map(|_| None).collect(),
+            count: 0,
+            hasher,
+        }
+    }
+
+    fn mask(&self) -> usize {
+        self.slots.len() - 1
+    }
+
+    // How far the item at slot `i` is from the slot its hash points to.
+    fn distance(&self, hash: usize, i: usize) -> usize {
+        i.wrapping_sub(hash) & self.mask()
+    }
+
+    fn find(&self, key: &K) -> Option<usize> {
+        let hash = hasher::hash_key(&self.hasher, key);
+        let mut i = hash & self.mask();
+        let mut dist = 0;
+        // Load is below 1, so there's always an empty slot to stop at.
+        while let Some(bucket) = &self.slots[i] {
+            // Robin Hood keeps runs sorted by distance: once the items get
+            // closer to home than we are, `key` can't be further on.
+            if self.distance(bucket.hash, i) < dist {
+                return None;
+            }
+            if bucket.hash == hash && bucket.key == *key {
+                return Some(i);
+            }
+            i = (i + 1) & self.mask();
+            dist += 1;
+        }
+        None
+    }
+
+    // Stores a bucket whose key isn't in the table, returning its slot.
+    fn place(&mut self, mut bucket: Bucket<K, V>) -> usize {
+        let mut i = bucket.hash & self.mask();
+        let mut dist = 0;
+        let mut placed = None;
+        loop {
+            let existing_dist = match &self.slots[i] {
+                None => {
+                    self.slots[i] = Some(bucket);
+                    return placed.unwrap_or(i);
+                }
+                Some(existing) => self.distance(existing.hash, i),
+            };
+            if existing_dist < dist {
+                if let Some(existing) = &mut self.slots[i] {
+                    mem::swap(existing, &mut bucket);
+                }
+                placed.get_or_insert(i);
+                dist = existing_dist;
+            }
+            i = (i + 1) & self.mask();
+            dist += 1;
+        }
+    }
+
+    fn grow(&mut self) {
+        let capacity = self.slots.len() * 2;
+        let slots = mem::replace(&mut self.slots, (0..capacity).map(|_| None).collect());
+        for bucket in slots.into_iter().flatten() {
+            self.place(bucket);
+        }
+    }
+
+    // Adds a key that isn't in the table, returning its slot.
+    fn push(&mut self, key: K, value: V) -> usize {
+        if (self.count + 1) * MAX_LOAD_DEN > self.slots.len() * MAX_LOAD_NUM {
+            self.grow();
+        }
+        self.count += 1;
+        let hash = hasher::hash_key(&self.hasher, &key);
+        self.place(Bucket { hash, key, value })
+    }
+
+    /// Inserts `value` under `key`, replacing and returning the previous
+    /// value if the key was already present.
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        if let Some(i) = self.find(&key) {
+            return self.slots[i]
+                .as_mut()
+                .map(|bucket| mem::replace(&mut bucket.value, value));
+        }
+        self.push(key, value);
+        None
+    }
+
+    #[must_use]
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let i = self.find(key)?;
+        self.slots[i].as_ref().map(|bucket| &bucket.value)
+    }
+
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let i = self.find(key)?;
+        self.slots[i].as_mut().map(|bucket| &mut bucket.value)
+    }
+
+    /// Returns the value of `key`, inserting the result of `f` first if the
+    /// key is missing.
+    pub fn get_or_insert_with<F>(&mut self, key: K, f: F) -> &mut V
+    where
+        F: FnOnce() -> V,
+    {
+        let i = match self.find(&key) {
+            Some(i) => i,
+            None => self.push(key, f()),
+        };
+        match &mut self.slots[i] {
+            Some(bucket) => &mut bucket.value,
+            None => unreachable!("slot {i} was just filled"),
+        }
+    }
+
+    /// Removes `key` from the table, returning its value if it was present.
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        let mut i = self.find(key)?;
+        let removed = self.slots[i].take();
+        self.count -= 1;
+        // Shift the rest of the run back so no probe stops early at the hole.
+        loop {
+            let next = (i + 1) & self.mask();
+            match &self.slots[next] {
+                Some(bucket) if self.distance(bucket.hash, next) > 0 => {
+                    self.slots[i] = self.slots[next].take();
+                    i = next;
+                }
+                _ => break,
+            }
+        }
+        removed.map(|bucket| bucket.value)
+    }
+}
+
+impl<K, V, S> OpenTable<K, V, S> {
+    /// Number of items stored in the table.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Iterates over the items in no particular order.
+    #[must_use]
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        Iter {
+            slots: self.slots.iter(),
+            remaining: self.count,
+        }
+    }
+
+    /// Reports how the items are spread over the slots. A chain here is the
+    /// probe sequence needed to find an item, so an item sitting in its
+    /// ideal slot has a chain of 1.
+    #[must_use]
+    pub fn stats(&self) -> TableStats {
+        let mask = self.slots.len() - 1;
+        let mut histogram = vec![0];
+        let mut probes = 0;
+        for (i, slot) in self.slots.iter().enumerate() {
+            let chain = slot
+                .as_ref()
+                .map_or(0, |bucket| (i.wrapping_sub(bucket.hash) & mask) + 1);
+            if histogram.len() <= chain {
+                histogram.resize(chain + 1, 0);
+            }
+            histogram[chain] += 1;
+            probes += chain;
+        }
+
+        let buckets = self.slots.len();
+        let empty_buckets = histogram[0];
+        TableStats {
+            len: self.count,
+            buckets,
+            load_factor: self.count as f64 / buckets as f64,
+            empty_buckets,
+            empty_ratio: empty_buckets as f64 / buckets as f64,
+            max_chain: histogram.len() - 1,
+            mean_chain: if self.count == 0 {
+                0.0
+            } else {
+                probes as f64 / self.count as f64
+            },
+            histogram,
+        }
+    }
+}
+
+impl<K, V, S> Default for OpenTable<K, V, S>
+where
+    K: Hashable + PartialEq,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        Self::with_hasher(DEFAULT_CAPACITY, S::default())
+    }
+}
+
+impl<K, V, S> Map<K, V> for OpenTable<K, V, S>
+where
+    K: Hashable + PartialEq,
+    S: BuildHasher + Default,
+{
+    type Iter<'a>
+        = Iter<'a, K, V>
+    where
+        Self: 'a,
+        K: 'a,
+        V: 'a;
+
+    fn len(&self) -> usize {
+        self.count
+    }
+
+    fn get(&self, key: &K) -> Option<&V> {
+        OpenTable::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        OpenTable::get_mut(self, key)
+    }
+
+    fn insert(&mut self, key: K, value: V) -> Result<Option<V>> {
+        Ok(OpenTable::insert(self, key, value))
+    }
+
+    fn get_or_try_insert_with<F>(&mut self, key: K, f: F) -> Result<&mut V>
+    where
+        F: FnOnce() -> V,
+    {
+        Ok(self.get_or_insert_with(key, f))
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        OpenTable::remove(self, key)
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        OpenTable::iter(self)
+    }
+
+    fn stats(&self) -> TableStats {
+        OpenTable::stats(self)
+    }
+}
+
+/// Borrowing iterator over an [`OpenTable`], created by [`OpenTable::iter`].
+pub struct Iter<'a, K, V> {
+    slots: slice::Iter<'a, Option<Bucket<K, V>>>,
+    remaining: usize,
+}
+
+impl<'a, K, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let bucket = self.slots.by_ref().flatten().next()?;
+        self.remaining -= 1;
+        Some((&bucket.key, &bucket.value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V> ExactSizeIterator for Iter<'_, K, V> {}
+
+impl<K, V> FusedIterator for Iter<'_, K, V> {}
+
+impl<'a, K, V, S> IntoIterator for &'a OpenTable<K, V, S> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::hash::{BuildHasherDefault, Hasher};
+
+    use super::*;
+
+    // Sends every key to the same slot, so all items share one long run.
+    #[derive(Default)]
+    struct Constant;
+
+    impl Hasher for Constant {
+        fn write(&mut self, _bytes: &[u8]) {}
+
+        fn finish(&self) -> u64 {
+            5
+        }
+    }
+
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: u32) -> u32 {
+            u32::try_from(self.next() % u64::from(n)).unwrap()
+        }
+    }
+
+    fn check<S: BuildHasher>(table: &OpenTable<u32, u32, S>, oracle: &HashMap<u32, u32>) {
+        assert_eq!(table.len(), oracle.len());
+        for (k, v) in oracle {
+            assert_eq!(table.get(k), Some(v));
+        }
+        let mut items = table.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        let mut expected = oracle.iter().map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+        items.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(items, expected);
+    }
+
+    fn random_ops<S: BuildHasher>(mut table: OpenTable<u32, u32, S>, ops: usize) {
+        let mut oracle = HashMap::new();
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..ops {
+            let key = rng.below(500);
+            if rng.below(3) == 0 {
+                assert_eq!(table.remove(&key), oracle.remove(&key));
+            } else {
+                let value = rng.below(u32::MAX);
+                assert_eq!(table.insert(key, value), oracle.insert(key, value));
+            }
+        }
+        check(&table, &oracle);
+    }
+
+    #[test]
+    fn insert_and_get() {
+        let mut table = OpenTable::new(1);
+        assert_eq!(table.insert("Peter Parker".to_string(), 1), None);
+        assert_eq!(table.insert("Tony Stark".to_string(), 2), None);
+        assert_eq!(table.insert("Peter Parker".to_string(), 3), Some(1));
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(&"Peter Parker".to_string()), Some(&3));
+        assert_eq!(table.get(&"Bruce Wayne".to_string()), None);
+        *table.get_mut(&"Tony Stark".to_string()).unwrap() += 10;
+        assert_eq!(table.get(&"Tony Stark".to_string()), Some(&12));
+    }
+
+    #[test]
+    fn grows() {
+        let mut table = OpenTable::new(1);
+        for i in 0..10_000u32 {
+            table.insert(i, i * 2);
+        }
+        assert_eq!(table.len(), 10_000);
+        assert!(table.slots.len() * MAX_LOAD_NUM >= 10_000 * MAX_LOAD_DEN);
+        for i in 0..10_000u32 {
+            assert_eq!(table.get(&i), Some(&(i * 2)));
+        }
+    }
+
+    #[test]
+    fn randomized_against_hashmap() {
+        random_ops(OpenTable::new(1), 20_000);
+    }
+
+    #[test]
+    fn colliding_hashes() {
+        // Long runs that wrap around the end of the slot array.
+        random_ops(
+            OpenTable::with_hasher(16, BuildHasherDefault::<Constant>::default()),
+            2_000,
+        );
+    }
+
+    #[test]
+    fn get_or_insert_with() {
+        let mut table: OpenTable<u32, Vec<u32>> = OpenTable::default();
+        for i in 0..100u32 {
+            table.get_or_insert_with(i % 7, Vec::new).push(i);
+        }
+        assert_eq!(table.len(), 7);
+        assert_eq!(table.get(&3).map(Vec::len), Some(14));
+        assert_eq!(table.get(&3).unwrap()[..3], [3, 10, 17]);
+    }
+
+    #[test]
+    fn stats() {
+        let mut table = OpenTable::with_hasher(16, BuildHasherDefault::<Constant>::default());
+        for i in 0..4u32 {
+            table.insert(i, i);
+        }
+        let stats = table.stats();
+        assert_eq!(stats.len, 4);
+        assert_eq!(stats.buckets, 16);
+        assert_eq!(stats.empty_buckets, 12);
+        assert_eq!(stats.max_chain, 4);
+        assert_eq!(stats.histogram, vec![12, 1, 1, 1, 1]);
+        assert!((stats.mean_chain - 2.5).abs() < f64::EPSILON);
+    }
+}
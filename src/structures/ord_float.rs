@@ -0,0 +1,117 @@
+use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
+
+use super::paged_btree::FixedSize;
+
+/// An `f32` with a total order, so it can be used as a [`BTree`] key.
+///
+/// Values are compared with [`f32::total_cmp`]: `-0.0` sorts before `0.0`
+/// and NaNs are ordered by their sign bit instead of breaking the ordering,
+/// so a NaN with the sign bit set sorts before every other value and one
+/// without it after every other value.
+///
+/// [`BTree`]: super::btree::BTree
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OrdF32(pub f32);
+
+impl PartialEq for OrdF32 {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrdF32 {}
+
+impl PartialOrd for OrdF32 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OrdF32 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.total_cmp(&other.0)
+    }
+}
+
+impl From<f32> for OrdF32 {
+    fn from(value: f32) -> Self {
+        OrdF32(value)
+    }
+}
+
+impl From<OrdF32> for f32 {
+    fn from(value: OrdF32) -> Self {
+        value.0
+    }
+}
+
+impl Display for OrdF32 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FixedSize for OrdF32 {
+    const SIZE: usize = f32::SIZE;
+
+    fn encode(&self, buf: &mut [u8]) {
+        self.0.encode(buf);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        OrdF32(f32::decode(buf))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn total_order() {
+        let mut values = [
+            OrdF32(f32::NAN),
+            OrdF32(1.5),
+            OrdF32(-0.0),
+            OrdF32(f32::NEG_INFINITY),
+            OrdF32(0.0),
+            OrdF32(-3.0),
+        ];
+        values.sort();
+
+        let sorted = values.iter().map(|v| v.0.to_string()).collect::<Vec<_>>();
+        assert_eq!(sorted, vec!["-inf", "-3", "-0", "0", "1.5", "NaN"]);
+        assert_eq!(OrdF32(f32::NAN), OrdF32(f32::NAN));
+        assert!(OrdF32(-0.0) < OrdF32(0.0));
+    }
+
+    #[test]
+    fn negative_nan() {
+        let negative = OrdF32(-f32::NAN);
+        assert!(negative.0.is_sign_negative());
+        assert!(negative < OrdF32(f32::NEG_INFINITY));
+        assert!(negative < OrdF32(f32::NAN));
+        assert_ne!(negative, OrdF32(f32::NAN));
+
+        let mut values = [OrdF32(f32::NAN), OrdF32(0.0), negative, OrdF32(-1.0)];
+        values.sort();
+        assert!(values[0].0.is_nan() && values[0].0.is_sign_negative());
+        assert!(values[3].0.is_nan() && values[3].0.is_sign_positive());
+    }
+
+    #[test]
+    fn composite_keys() {
+        // (rating, rating count, sofifa id)
+        let mut keys = [
+            (OrdF32(4.0), 1200, 7),
+            (OrdF32(4.0), 1500, 3),
+            (OrdF32(4.5), 1001, 9),
+            (OrdF32(4.0), 1200, 2),
+        ];
+        keys.sort();
+
+        let ids = keys.iter().map(|k| k.2).collect::<Vec<_>>();
+        assert_eq!(ids, vec![2, 7, 3, 9]);
+    }
+}
@@ -0,0 +1,1099 @@
+// B-tree whose nodes live in fixed-size pages of a file
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::mem::{self, size_of};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Result};
+
+use super::btree::PAGE;
+
+const MAGIC: &[u8; 4] = b"FSBT";
+const HEADER_PAGE: u32 = 0;
+// Flag of pages freed by a merge, which hold the id of the next free page.
+// The free list ends at the header page, which is never free.
+const FREE_PAGE: u8 = 2;
+// Pages kept in memory at once.
+const POOL_SIZE: usize = 64;
+// Leaf flag, padding and key count at the start of every node page.
+const NODE_HEADER: usize = 4;
+const CHILD_SIZE: usize = size_of::<u32>();
+// Every node below the root has at least one key and every internal node
+// two children, so no tree that fits a `u64` length is deeper than this. A
+// deeper walk means the child links of a corrupt file loop.
+const MAX_DEPTH: usize = 64;
+
+/// Types with a fixed-size little-endian encoding, usable as keys and values
+/// of a [`PagedBTree`].
+pub trait FixedSize: Sized {
+    const SIZE: usize;
+
+    fn encode(&self, buf: &mut [u8]);
+    fn decode(buf: &[u8]) -> Self;
+}
+
+macro_rules! impl_fixed_size {
+    ($($t:ty),*) => {
+        $(
+            impl FixedSize for $t {
+                const SIZE: usize = size_of::<$t>();
+
+                fn encode(&self, buf: &mut [u8]) {
+                    buf[..Self::SIZE].copy_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(buf: &[u8]) -> Self {
+                    let mut bytes = [0; size_of::<$t>()];
+                    bytes.copy_from_slice(&buf[..Self::SIZE]);
+                    <$t>::from_le_bytes(bytes)
+                }
+            }
+        )*
+    };
+}
+
+impl_fixed_size!(u16, u32, u64, i32, i64, f32, f64);
+
+impl<A: FixedSize, B: FixedSize> FixedSize for (A, B) {
+    const SIZE: usize = A::SIZE + B::SIZE;
+
+    fn encode(&self, buf: &mut [u8]) {
+        self.0.encode(buf);
+        self.1.encode(&mut buf[A::SIZE..]);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        (A::decode(buf), B::decode(&buf[A::SIZE..]))
+    }
+}
+
+impl<A: FixedSize, B: FixedSize, C: FixedSize> FixedSize for (A, B, C) {
+    const SIZE: usize = A::SIZE + B::SIZE + C::SIZE;
+
+    fn encode(&self, buf: &mut [u8]) {
+        self.0.encode(buf);
+        self.1.encode(&mut buf[A::SIZE..]);
+        self.2.encode(&mut buf[A::SIZE + B::SIZE..]);
+    }
+
+    fn decode(buf: &[u8]) -> Self {
+        (
+            A::decode(buf),
+            B::decode(&buf[A::SIZE..]),
+            C::decode(&buf[A::SIZE + B::SIZE..]),
+        )
+    }
+}
+
+// Depth of the next level of a walk down the tree, failing once it's too
+// deep to be anything but a loop.
+fn deeper(depth: usize) -> Result<usize> {
+    if depth >= MAX_DEPTH {
+        bail!("Corrupt page file: child links loop");
+    }
+    Ok(depth + 1)
+}
+
+struct Frame {
+    page_id: u32,
+    data: Vec<u8>,
+    dirty: bool,
+    last_used: u64,
+}
+
+/// Reads and writes pages of the file through a small LRU buffer pool.
+struct Pager {
+    file: File,
+    page_count: u32,
+    // First page of the list of freed pages, reused before the file grows.
+    free: u32,
+    frames: Vec<Frame>,
+    clock: u64,
+}
+
+impl Pager {
+    fn new(file: File, page_count: u32) -> Self {
+        Pager {
+            file,
+            page_count,
+            free: HEADER_PAGE,
+            frames: Vec::with_capacity(POOL_SIZE),
+            clock: 0,
+        }
+    }
+
+    fn page(&mut self, page_id: u32) -> Result<&[u8]> {
+        let i = self.frame_index(page_id)?;
+        Ok(&self.frames[i].data)
+    }
+
+    fn page_mut(&mut self, page_id: u32) -> Result<&mut [u8]> {
+        let i = self.frame_index(page_id)?;
+        self.frames[i].dirty = true;
+        Ok(&mut self.frames[i].data)
+    }
+
+    fn allocate(&mut self) -> Result<u32> {
+        if self.free != HEADER_PAGE {
+            let page_id = self.free;
+            let page = self.page_mut(page_id)?;
+            if page[0] != FREE_PAGE {
+                bail!("Corrupt page file: page {} isn't free", page_id);
+            }
+            let next = u32::decode(&page[4..]);
+            page.fill(0);
+            self.free = next;
+            return Ok(page_id);
+        }
+
+        let page_id = self.page_count;
+        self.page_count = self
+            .page_count
+            .checked_add(1)
+            .ok_or_else(|| anyhow!("Page file is full"))?;
+        let i = self.free_frame()?;
+        self.frames[i] = Frame {
+            page_id,
+            data: vec![0; PAGE],
+            dirty: true,
+            last_used: self.tick(),
+        };
+        Ok(page_id)
+    }
+
+    /// Adds `page_id` to the free list, to be handed out again by
+    /// [`Pager::allocate`].
+    fn release(&mut self, page_id: u32) -> Result<()> {
+        let next = self.free;
+        let page = self.page_mut(page_id)?;
+        page.fill(0);
+        page[0] = FREE_PAGE;
+        next.encode(&mut page[4..]);
+        self.free = page_id;
+        Ok(())
+    }
+
+    fn frame_index(&mut self, page_id: u32) -> Result<usize> {
+        if page_id >= self.page_count {
+            bail!("Page {} out of bounds", page_id);
+        }
+        let tick = self.tick();
+        if let Some(i) = self.frames.iter().position(|f| f.page_id == page_id) {
+            self.frames[i].last_used = tick;
+            return Ok(i);
+        }
+
+        let mut data = vec![0; PAGE];
+        self.file
+            .seek(SeekFrom::Start(u64::from(page_id) * PAGE as u64))?;
+        self.file.read_exact(&mut data)?;
+        let i = self.free_frame()?;
+        self.frames[i] = Frame {
+            page_id,
+            data,
+            dirty: false,
+            last_used: tick,
+        };
+        Ok(i)
+    }
+
+    /// Returns the index of a frame that can be overwritten, writing back
+    /// the least recently used page if the pool is full.
+    fn free_frame(&mut self) -> Result<usize> {
+        if self.frames.len() < POOL_SIZE {
+            self.frames.push(Frame {
+                page_id: u32::MAX,
+                data: Vec::new(),
+                dirty: false,
+                last_used: 0,
+            });
+            return Ok(self.frames.len() - 1);
+        }
+        let i = self
+            .frames
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, f)| f.last_used)
+            .map(|(i, _)| i)
+            .ok_or_else(|| anyhow!("Buffer pool is empty"))?;
+        self.write_back(i)?;
+        Ok(i)
+    }
+
+    fn write_back(&mut self, i: usize) -> Result<()> {
+        let frame = &mut self.frames[i];
+        if frame.dirty {
+            self.file
+                .seek(SeekFrom::Start(u64::from(frame.page_id) * PAGE as u64))?;
+            self.file.write_all(&frame.data)?;
+            frame.dirty = false;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        for i in 0..self.frames.len() {
+            self.write_back(i)?;
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+}
+
+struct DiskNode<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    children: Vec<u32>,
+}
+
+impl<K, V> DiskNode<K, V>
+where
+    K: FixedSize,
+    V: FixedSize,
+{
+    fn leaf() -> Self {
+        DiskNode {
+            keys: Vec::new(),
+            values: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// Decodes a node page, checking it against the `max_keys` a node can
+    /// hold and the `page_count` pages its children can be in, so a corrupt
+    /// file is reported instead of indexed out of bounds.
+    fn read(page: &[u8], max_keys: usize, page_count: u32) -> Result<Self> {
+        let is_leaf = match page[0] {
+            0 => false,
+            1 => true,
+            flag => bail!("Corrupt page: unknown node flag {}", flag),
+        };
+        let n = usize::from(u16::decode(&page[2..]));
+        if n > max_keys {
+            bail!("Corrupt page: {} keys, at most {} fit", n, max_keys);
+        }
+
+        let mut offset = NODE_HEADER;
+        let mut keys = Vec::with_capacity(n);
+        for _ in 0..n {
+            keys.push(K::decode(&page[offset..]));
+            offset += K::SIZE;
+        }
+        let mut values = Vec::with_capacity(n);
+        for _ in 0..n {
+            values.push(V::decode(&page[offset..]));
+            offset += V::SIZE;
+        }
+        let mut children = Vec::new();
+        if !is_leaf {
+            for _ in 0..=n {
+                let child = u32::decode(&page[offset..]);
+                if child == HEADER_PAGE || child >= page_count {
+                    bail!("Corrupt page: child page {} out of bounds", child);
+                }
+                children.push(child);
+                offset += CHILD_SIZE;
+            }
+        }
+        Ok(DiskNode {
+            keys,
+            values,
+            children,
+        })
+    }
+
+    fn write(&self, page: &mut [u8]) -> Result<()> {
+        page[0] = u8::from(self.is_leaf());
+        u16::try_from(self.keys.len())?.encode(&mut page[2..]);
+
+        let mut offset = NODE_HEADER;
+        for key in &self.keys {
+            key.encode(&mut page[offset..]);
+            offset += K::SIZE;
+        }
+        for value in &self.values {
+            value.encode(&mut page[offset..]);
+            offset += V::SIZE;
+        }
+        for child in &self.children {
+            child.encode(&mut page[offset..]);
+            offset += CHILD_SIZE;
+        }
+        Ok(())
+    }
+}
+
+/// A [`BTree`](super::btree::BTree) stored in a page file, so it can be
+/// persisted and reopened without being rebuilt. Only a few pages are kept
+/// in memory at a time; call [`PagedBTree::flush`] or [`PagedBTree::close`]
+/// to make sure every change reached the disk. Dropping the tree flushes it
+/// too, but any error is lost.
+pub struct PagedBTree<K, V>
+where
+    K: Ord + Copy + FixedSize,
+    V: Clone + FixedSize,
+{
+    pager: Pager,
+    root: u32,
+    len: usize,
+    closed: bool,
+    _entries: PhantomData<(K, V)>,
+}
+
+impl<K, V> PagedBTree<K, V>
+where
+    K: Ord + Copy + FixedSize,
+    V: Clone + FixedSize,
+{
+    const ORDER: usize = (PAGE - NODE_HEADER - CHILD_SIZE) / (K::SIZE + V::SIZE + CHILD_SIZE);
+    const DEGREE: usize = Self::ORDER / 2;
+    const MAX_KEYS: usize = 2 * Self::DEGREE - 1;
+    const MID_KEYS: usize = Self::DEGREE - 1;
+    const MIN_KEYS: usize = Self::DEGREE - 1;
+
+    /// Creates an empty tree, overwriting the file at `path`.
+    ///
+    /// # Errors
+    /// Fails if the file can't be created or if a page can't hold at least
+    /// three entries of this key and value type.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+        if Self::ORDER < 4 {
+            bail!("Keys and values are too large to fit in a page");
+        }
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        let mut pager = Pager::new(file, 0);
+        pager.allocate()?;
+        let root = pager.allocate()?;
+        DiskNode::<K, V>::leaf().write(pager.page_mut(root)?)?;
+
+        let mut tree = PagedBTree {
+            pager,
+            root,
+            len: 0,
+            closed: false,
+            _entries: PhantomData,
+        };
+        tree.flush()?;
+        Ok(tree)
+    }
+
+    /// Opens a tree previously written by [`PagedBTree::create`].
+    ///
+    /// # Errors
+    /// Fails if the file can't be read, isn't a page file or was written
+    /// with keys or values of a different size.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        let page_count = u32::try_from(file.metadata()?.len() / PAGE as u64)?;
+        if page_count < 2 {
+            bail!("Not a page file");
+        }
+        let mut pager = Pager::new(file, page_count);
+
+        let header = pager.page(HEADER_PAGE)?;
+        if &header[0..4] != MAGIC {
+            bail!("Not a page file");
+        }
+        let root = u32::decode(&header[4..]);
+        let len = usize::try_from(u64::decode(&header[8..]))?;
+        let key_size = u32::decode(&header[16..]) as usize;
+        let value_size = u32::decode(&header[20..]) as usize;
+        let free = u32::decode(&header[24..]);
+        if key_size != K::SIZE || value_size != V::SIZE {
+            bail!(
+                "Page file stores {}-byte keys and {}-byte values, expected {} and {}",
+                key_size,
+                value_size,
+                K::SIZE,
+                V::SIZE
+            );
+        }
+        if root == HEADER_PAGE || root >= page_count {
+            bail!("Corrupt page file: root page {} out of bounds", root);
+        }
+        if free >= page_count {
+            bail!("Corrupt page file: free page {} out of bounds", free);
+        }
+        pager.free = free;
+        if len > page_count as usize * Self::MAX_KEYS {
+            bail!(
+                "Corrupt page file: {} entries don't fit in {} pages",
+                len,
+                page_count
+            );
+        }
+
+        Ok(PagedBTree {
+            pager,
+            root,
+            len,
+            closed: false,
+            _entries: PhantomData,
+        })
+    }
+
+    /// Writes every modified page and the header back to the file.
+    ///
+    /// # Errors
+    /// Fails if the file can't be written.
+    pub fn flush(&mut self) -> Result<()> {
+        let root = self.root;
+        let len = self.len as u64;
+        let free = self.pager.free;
+        let header = self.pager.page_mut(HEADER_PAGE)?;
+        header[0..4].copy_from_slice(MAGIC);
+        root.encode(&mut header[4..]);
+        len.encode(&mut header[8..]);
+        u32::try_from(K::SIZE)?.encode(&mut header[16..]);
+        u32::try_from(V::SIZE)?.encode(&mut header[20..]);
+        free.encode(&mut header[24..]);
+        self.pager.flush()
+    }
+
+    /// Flushes the tree and closes its file.
+    ///
+    /// # Errors
+    /// Fails if the file can't be written.
+    pub fn close(mut self) -> Result<()> {
+        self.closed = true;
+        self.flush()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn load(&mut self, page_id: u32) -> Result<DiskNode<K, V>> {
+        let page_count = self.pager.page_count;
+        DiskNode::read(self.pager.page(page_id)?, Self::MAX_KEYS, page_count)
+    }
+
+    fn store(&mut self, page_id: u32, node: &DiskNode<K, V>) -> Result<()> {
+        node.write(self.pager.page_mut(page_id)?)
+    }
+
+    /// # Errors
+    /// Fails if a page can't be read or written.
+    pub fn insert(&mut self, key: K, value: V) -> Result<()> {
+        let root = self.load(self.root)?;
+        if root.keys.len() == Self::MAX_KEYS {
+            let new_root = DiskNode {
+                keys: Vec::new(),
+                values: Vec::new(),
+                children: vec![self.root],
+            };
+            self.root = self.pager.allocate()?;
+            self.split_child(self.root, new_root, 0)?;
+        }
+
+        let mut page_id = self.root;
+        let mut depth = 0;
+        loop {
+            depth = deeper(depth)?;
+            let mut node = self.load(page_id)?;
+            let mut i = node.keys.len();
+            while i > 0 && key < node.keys[i - 1] {
+                i -= 1;
+            }
+            if node.is_leaf() {
+                node.keys.insert(i, key);
+                node.values.insert(i, value);
+                self.store(page_id, &node)?;
+                break;
+            }
+            let child = self.load(node.children[i])?;
+            if child.keys.len() == Self::MAX_KEYS {
+                node = self.split_child(page_id, node, i)?;
+                if key >= node.keys[i] {
+                    i += 1;
+                }
+            }
+            page_id = node.children[i];
+        }
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Splits the full child `child_index` of `parent`, storing the three
+    /// affected pages and returning the updated parent.
+    fn split_child(
+        &mut self,
+        parent_id: u32,
+        mut parent: DiskNode<K, V>,
+        child_index: usize,
+    ) -> Result<DiskNode<K, V>> {
+        let child_id = parent.children[child_index];
+        let mut child = self.load(child_id)?;
+
+        let right = DiskNode {
+            keys: child.keys.split_off(Self::MID_KEYS + 1),
+            values: child.values.split_off(Self::MID_KEYS + 1),
+            children: if child.is_leaf() {
+                Vec::new()
+            } else {
+                child.children.split_off(Self::MID_KEYS + 1)
+            },
+        };
+        let middle_key = child
+            .keys
+            .pop()
+            .ok_or_else(|| anyhow!("Split empty node"))?;
+        let middle_value = child
+            .values
+            .pop()
+            .ok_or_else(|| anyhow!("Split empty node"))?;
+
+        let right_id = self.pager.allocate()?;
+        self.store(right_id, &right)?;
+        self.store(child_id, &child)?;
+
+        parent.keys.insert(child_index, middle_key);
+        parent.values.insert(child_index, middle_value);
+        parent.children.insert(child_index + 1, right_id);
+        self.store(parent_id, &parent)?;
+        Ok(parent)
+    }
+
+    /// Removes one entry with the given key and value, returning the removed
+    /// value, or `None` if no such entry exists. Pages freed by merging
+    /// nodes are reused by later inserts, but the file never shrinks.
+    ///
+    /// # Errors
+    /// Fails if a page can't be read or written.
+    pub fn remove(&mut self, key: &K, value: &V) -> Result<Option<V>>
+    where
+        V: PartialEq,
+    {
+        let removed = self.remove_from(self.root, key, value, 0)?;
+        if removed.is_some() {
+            self.len -= 1;
+            let root = self.load(self.root)?;
+            if root.keys.is_empty() && !root.is_leaf() {
+                self.pager.release(self.root)?;
+                self.root = root.children[0];
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Removes one entry matching both `key` and `value` from the subtree
+    /// rooted at `page_id`. Children that underflow on the way back up are
+    /// rebalanced, but the node itself may be left with too few keys.
+    fn remove_from(&mut self, page_id: u32, key: &K, value: &V, depth: usize) -> Result<Option<V>>
+    where
+        V: PartialEq,
+    {
+        let depth = deeper(depth)?;
+        let mut node = self.load(page_id)?;
+        // Duplicate keys may be spread over every separator equal to `key`
+        // and over all the children around them.
+        let mut lo = 0;
+        while lo < node.keys.len() && node.keys[lo] < *key {
+            lo += 1;
+        }
+        let mut hi = lo;
+        while hi < node.keys.len() && node.keys[hi] <= *key {
+            hi += 1;
+        }
+
+        for i in lo..hi {
+            if node.keys[i] == *key && node.values[i] == *value {
+                if node.is_leaf() {
+                    node.keys.remove(i);
+                    let removed = node.values.remove(i);
+                    self.store(page_id, &node)?;
+                    return Ok(Some(removed));
+                }
+                let (pred_key, pred_value) = self.remove_max(node.children[i], depth)?;
+                node.keys[i] = pred_key;
+                let removed = mem::replace(&mut node.values[i], pred_value);
+                self.fix_child(page_id, node, i)?;
+                return Ok(Some(removed));
+            }
+        }
+
+        if node.is_leaf() {
+            return Ok(None);
+        }
+        for i in lo..=hi {
+            if let Some(removed) = self.remove_from(node.children[i], key, value, depth)? {
+                self.fix_child(page_id, node, i)?;
+                return Ok(Some(removed));
+            }
+        }
+        Ok(None)
+    }
+
+    fn remove_max(&mut self, page_id: u32, depth: usize) -> Result<(K, V)> {
+        let depth = deeper(depth)?;
+        let mut node = self.load(page_id)?;
+        if node.is_leaf() {
+            let key = node
+                .keys
+                .pop()
+                .ok_or_else(|| anyhow!("Remove from empty node"))?;
+            let value = node
+                .values
+                .pop()
+                .ok_or_else(|| anyhow!("Remove from empty node"))?;
+            self.store(page_id, &node)?;
+            Ok((key, value))
+        } else {
+            let last = node.children.len() - 1;
+            let res = self.remove_max(node.children[last], depth)?;
+            self.fix_child(page_id, node, last)?;
+            Ok(res)
+        }
+    }
+
+    /// Restores the minimum fill of child `child_index` of `parent`, either
+    /// by borrowing a key through the parent from a sibling or by merging
+    /// it with one, and stores the parent.
+    fn fix_child(
+        &mut self,
+        parent_id: u32,
+        mut parent: DiskNode<K, V>,
+        child_index: usize,
+    ) -> Result<()> {
+        let child = self.load(parent.children[child_index])?;
+        if child.keys.len() < Self::MIN_KEYS {
+            let left = match child_index.checked_sub(1) {
+                Some(i) => Some(self.load(parent.children[i])?),
+                None => None,
+            };
+            let right = match parent.children.get(child_index + 1) {
+                Some(&page_id) => Some(self.load(page_id)?),
+                None => None,
+            };
+            match (left, right) {
+                (Some(left), _) if left.keys.len() > Self::MIN_KEYS => {
+                    self.rotate_right(&mut parent, child_index - 1, left, child)?;
+                }
+                (_, Some(right)) if right.keys.len() > Self::MIN_KEYS => {
+                    self.rotate_left(&mut parent, child_index, child, right)?;
+                }
+                (Some(left), _) => {
+                    self.merge_children(&mut parent, child_index - 1, left, child)?;
+                }
+                (None, Some(right)) => {
+                    self.merge_children(&mut parent, child_index, child, right)?;
+                }
+                (None, None) => bail!("Node without siblings"),
+            }
+        }
+        self.store(parent_id, &parent)
+    }
+
+    /// Moves the last key of `children[index]` up into the parent and the
+    /// separator down into the front of `children[index + 1]`.
+    fn rotate_right(
+        &mut self,
+        parent: &mut DiskNode<K, V>,
+        index: usize,
+        mut left: DiskNode<K, V>,
+        mut right: DiskNode<K, V>,
+    ) -> Result<()> {
+        let key = left
+            .keys
+            .pop()
+            .ok_or_else(|| anyhow!("Rotate from empty node"))?;
+        let value = left
+            .values
+            .pop()
+            .ok_or_else(|| anyhow!("Rotate from empty node"))?;
+        right
+            .keys
+            .insert(0, mem::replace(&mut parent.keys[index], key));
+        right
+            .values
+            .insert(0, mem::replace(&mut parent.values[index], value));
+        if let Some(child) = left.children.pop() {
+            right.children.insert(0, child);
+        }
+        self.store(parent.children[index], &left)?;
+        self.store(parent.children[index + 1], &right)
+    }
+
+    /// Moves the first key of `children[index + 1]` up into the parent and
+    /// the separator down into the back of `children[index]`.
+    fn rotate_left(
+        &mut self,
+        parent: &mut DiskNode<K, V>,
+        index: usize,
+        mut left: DiskNode<K, V>,
+        mut right: DiskNode<K, V>,
+    ) -> Result<()> {
+        let key = right.keys.remove(0);
+        let value = right.values.remove(0);
+        left.keys.push(mem::replace(&mut parent.keys[index], key));
+        left.values
+            .push(mem::replace(&mut parent.values[index], value));
+        if !right.is_leaf() {
+            left.children.push(right.children.remove(0));
+        }
+        self.store(parent.children[index], &left)?;
+        self.store(parent.children[index + 1], &right)
+    }
+
+    /// Merges `children[index + 1]` and the separator between them into
+    /// `children[index]`.
+    fn merge_children(
+        &mut self,
+        parent: &mut DiskNode<K, V>,
+        index: usize,
+        mut left: DiskNode<K, V>,
+        right: DiskNode<K, V>,
+    ) -> Result<()> {
+        let right_id = parent.children.remove(index + 1);
+        self.pager.release(right_id)?;
+        left.keys.push(parent.keys.remove(index));
+        left.values.push(parent.values.remove(index));
+        left.keys.extend(right.keys);
+        left.values.extend(right.values);
+        left.children.extend(right.children);
+        self.store(parent.children[index], &left)
+    }
+
+    /// # Errors
+    /// Fails if a page can't be read.
+    pub fn find(&mut self, key: K) -> Result<Option<V>> {
+        let mut page_id = self.root;
+        let mut depth = 0;
+        loop {
+            depth = deeper(depth)?;
+            let node = self.load(page_id)?;
+            let mut i = node.keys.len();
+            while i > 0 && key <= node.keys[i - 1] {
+                i -= 1;
+            }
+            if i < node.keys.len() && key == node.keys[i] {
+                return Ok(Some(node.values[i].clone()));
+            }
+            if node.is_leaf() {
+                return Ok(None);
+            }
+            page_id = node.children[i];
+        }
+    }
+
+    /// # Errors
+    /// Fails if a page can't be read.
+    pub fn get_all(&mut self) -> Result<Vec<(K, V)>> {
+        let mut res = Vec::with_capacity(self.len);
+        self.collect_all(self.root, &mut res, 0)?;
+        Ok(res)
+    }
+
+    fn collect_all(&mut self, page_id: u32, res: &mut Vec<(K, V)>, depth: usize) -> Result<()> {
+        let depth = deeper(depth)?;
+        let node = self.load(page_id)?;
+        for i in 0..node.keys.len() {
+            if !node.is_leaf() {
+                self.collect_all(node.children[i], res, depth)?;
+            }
+            res.push((node.keys[i], node.values[i].clone()));
+        }
+        if let Some(last) = node.children.last() {
+            self.collect_all(*last, res, depth)?;
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    /// Fails if a page can't be read.
+    pub fn get_greatest_n(&mut self, n: u32) -> Result<Vec<V>> {
+        let mut res = Vec::new();
+        self.collect_greatest(self.root, n as usize, &mut res, 0)?;
+        Ok(res)
+    }
+
+    fn collect_greatest(
+        &mut self,
+        page_id: u32,
+        n: usize,
+        res: &mut Vec<V>,
+        depth: usize,
+    ) -> Result<()> {
+        if res.len() >= n {
+            return Ok(());
+        }
+        let depth = deeper(depth)?;
+        let node = self.load(page_id)?;
+        for i in (0..node.keys.len()).rev() {
+            if let Some(child) = node.children.get(i + 1) {
+                self.collect_greatest(*child, n, res, depth)?;
+            }
+            if res.len() >= n {
+                return Ok(());
+            }
+            res.push(node.values[i].clone());
+        }
+        if let Some(first) = node.children.first() {
+            self.collect_greatest(*first, n, res, depth)?;
+        }
+        Ok(())
+    }
+}
+
+impl<K, V> Drop for PagedBTree<K, V>
+where
+    K: Ord + Copy + FixedSize,
+    V: Clone + FixedSize,
+{
+    fn drop(&mut self) {
+        // Errors can't be reported from here, call close to handle them.
+        if !self.closed {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::structures::ord_float::OrdF32;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("fifa_sorter_{}_{}.db", name, std::process::id()))
+    }
+
+    #[test]
+    fn insert_and_find() {
+        let path = temp_path("insert_and_find");
+        let mut tree = PagedBTree::create(&path).unwrap();
+        for i in 0..50_000u32 {
+            tree.insert((i * 7919) % 50_000, i).unwrap();
+        }
+
+        assert_eq!(tree.len(), 50_000);
+        assert_eq!(tree.find(0).unwrap(), Some(0));
+        assert_eq!(tree.find(7919).unwrap(), Some(1));
+        assert_eq!(tree.find(50_000).unwrap(), None);
+        let greatest = tree.get_greatest_n(3).unwrap();
+        let keys = greatest.iter().map(|i| (i * 7919) % 50_000);
+        assert!(keys.eq([49_999, 49_998, 49_997]));
+
+        tree.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reopen() {
+        let path = temp_path("reopen");
+        {
+            let mut tree = PagedBTree::create(&path).unwrap();
+            for i in 0..20_000u32 {
+                tree.insert(OrdF32(i as f32 / 4.0), i).unwrap();
+            }
+            tree.close().unwrap();
+        }
+
+        let mut tree = PagedBTree::<OrdF32, u32>::open(&path).unwrap();
+        assert_eq!(tree.len(), 20_000);
+        assert_eq!(tree.find(OrdF32(2.5)).unwrap(), Some(10));
+        assert_eq!(tree.get_greatest_n(2).unwrap(), vec![19_999, 19_998]);
+        tree.insert(OrdF32(-1.0), 42).unwrap();
+
+        let all = tree.get_all().unwrap();
+        assert_eq!(all.len(), 20_001);
+        assert_eq!(all[0], (OrdF32(-1.0), 42));
+        assert!(all.windows(2).all(|w| w[0].0 <= w[1].0));
+
+        tree.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn open_wrong_types() {
+        let path = temp_path("open_wrong_types");
+        PagedBTree::<OrdF32, u32>::create(&path)
+            .unwrap()
+            .close()
+            .unwrap();
+
+        assert!(PagedBTree::<i64, u32>::open(&path).is_err());
+        assert!(PagedBTree::<OrdF32, u32>::open(&path).is_ok());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(PagedBTree::<OrdF32, u32>::open(&path).is_err());
+    }
+
+    #[test]
+    fn composite_keys() {
+        let path = temp_path("composite_keys");
+        let mut tree = PagedBTree::create(&path).unwrap();
+        for id in 0..1000u32 {
+            tree.insert((OrdF32((id % 5) as f32), id % 7, id), id)
+                .unwrap();
+        }
+
+        let greatest = tree.get_greatest_n(3).unwrap();
+        assert_eq!(greatest, vec![979, 944, 909]);
+
+        tree.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn write_at(path: &Path, offset: u64, bytes: &[u8]) {
+        let mut file = OpenOptions::new().write(true).open(path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        file.write_all(bytes).unwrap();
+    }
+
+    fn read_all(path: &Path) -> Result<Vec<(u32, u32)>> {
+        PagedBTree::<u32, u32>::open(path)?.get_all()
+    }
+
+    fn filled(name: &str) -> PathBuf {
+        let path = temp_path(name);
+        let mut tree = PagedBTree::create(&path).unwrap();
+        for i in 0..20_000u32 {
+            tree.insert(i, i).unwrap();
+        }
+        tree.close().unwrap();
+        path
+    }
+
+    #[test]
+    fn truncated_file() {
+        let path = filled("truncated_file");
+        let size = std::fs::metadata(&path).unwrap().len();
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(size / 2).unwrap();
+        drop(file);
+
+        assert!(read_all(&path).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn garbled_pages() {
+        let path = filled("garbled_pages");
+        let tree = PagedBTree::<u32, u32>::open(&path).unwrap();
+        let root = tree.root;
+        tree.close().unwrap();
+        let pristine = std::fs::read(&path).unwrap();
+        let start = u64::from(root) * PAGE as u64;
+        let keys = u64::from(pristine[usize::try_from(start).unwrap() + 2]);
+        let children = start + NODE_HEADER as u64 + keys * 8;
+
+        let garbles: [(u64, &[u8]); 6] = [
+            // Unknown node flag.
+            (start, &[7]),
+            // More keys than fit in a page.
+            (start + 2, &[0xff, 0xff]),
+            // Child past the end of the file, or in the header page.
+            (children, &[0xff, 0xff, 0, 0]),
+            (children, &[0, 0, 0, 0]),
+            // Root pointing to itself.
+            (children, &root.to_le_bytes()),
+            // Root out of bounds.
+            (4, &[0xff, 0xff, 0xff, 0x7f]),
+        ];
+        for (offset, bytes) in garbles {
+            std::fs::write(&path, &pristine).unwrap();
+            write_at(&path, offset, bytes);
+            assert!(read_all(&path).is_err(), "{offset} {bytes:?}");
+        }
+
+        std::fs::write(&path, &pristine).unwrap();
+        assert_eq!(read_all(&path).unwrap().len(), 20_000);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reuse_freed_pages() {
+        let path = temp_path("reuse_freed_pages");
+        let mut tree = PagedBTree::create(&path).unwrap();
+        for i in 0..20_000u32 {
+            tree.insert(i, i).unwrap();
+        }
+        let pages = tree.pager.page_count;
+        for round in 0..5 {
+            for i in 0..20_000u32 {
+                assert_eq!(tree.remove(&i, &i).unwrap(), Some(i));
+            }
+            assert!(tree.is_empty());
+            // Reopening keeps the list of free pages.
+            if round == 2 {
+                tree.close().unwrap();
+                tree = PagedBTree::open(&path).unwrap();
+            }
+            for i in 0..20_000u32 {
+                tree.insert(i, i).unwrap();
+            }
+            assert_eq!(tree.pager.page_count, pages);
+        }
+        assert!(tree
+            .get_all()
+            .unwrap()
+            .into_iter()
+            .eq((0..20_000).map(|i| (i, i))));
+
+        tree.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn remove() {
+        let path = temp_path("remove");
+        let mut tree = PagedBTree::create(&path).unwrap();
+        // Few distinct keys, so duplicates span several pages.
+        for i in 0..30_000u32 {
+            tree.insert((i * 7919) % 300, i).unwrap();
+        }
+
+        assert_eq!(tree.remove(&5, &6).unwrap(), None);
+        for i in (0..30_000u32).filter(|i| i % 3 != 0) {
+            assert_eq!(tree.remove(&((i * 7919) % 300), &i).unwrap(), Some(i));
+        }
+        assert_eq!(tree.remove(&((7919 * 2) % 300), &2).unwrap(), None);
+        assert_eq!(tree.len(), 10_000);
+        tree.close().unwrap();
+
+        let mut tree = PagedBTree::<u32, u32>::open(&path).unwrap();
+        let mut expected = (0..30_000u32)
+            .filter(|i| i % 3 == 0)
+            .map(|i| ((i * 7919) % 300, i))
+            .collect::<Vec<_>>();
+        let mut all = tree.get_all().unwrap();
+        assert!(all.windows(2).all(|w| w[0].0 <= w[1].0));
+        all.sort_unstable();
+        expected.sort_unstable();
+        assert_eq!(all, expected);
+
+        for (key, value) in expected {
+            assert_eq!(tree.remove(&key, &value).unwrap(), Some(value));
+        }
+        assert!(tree.is_empty());
+        assert!(tree.get_all().unwrap().is_empty());
+        tree.insert(1, 1).unwrap();
+        assert_eq!(tree.find(1).unwrap(), Some(1));
+
+        tree.close().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+use std::hash::BuildHasher;
+use std::iter::FusedIterator;
+use std::num::NonZeroUsize;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::{thread, vec};
+
+use anyhow::Result;
+
+use super::hash_table::{self, HashTable, Hashable};
+use super::hasher::{self, Fnv1a};
+
+// Shards per available thread for tables created with `Default`, so threads
+// rarely wait on the same lock.
+const SHARDS_PER_THREAD: usize = 4;
+
+/// A [`HashTable`] split into shards, each behind its own lock, so several
+/// threads can insert and update at the same time through `&self`. Threads
+/// only contend when their keys fall in the same shard.
+#[derive(Debug)]
+pub struct ShardedTable<K, V, S = Fnv1a> {
+    shards: Vec<Mutex<HashTable<K, V, S>>>,
+    hasher: S,
+}
+
+impl<K, V> ShardedTable<K, V>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+{
+    /// Creates a table with `shards` shards.
+    #[must_use]
+    pub fn new(shards: usize) -> Self {
+        Self::with_hasher(shards, Fnv1a::default())
+    }
+}
+
+impl<K, V, S> ShardedTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher + Default,
+{
+    /// Like [`ShardedTable::new`], hashing keys with `hasher`.
+    pub fn with_hasher(shards: usize, hasher: S) -> Self {
+        let shards = (0..shards.max(1))
+            .map(|_| Mutex::new(HashTable::default()))
+            .collect();
+        ShardedTable { shards, hasher }
+    }
+
+    fn shard(&self, key: &K) -> MutexGuard<'_, HashTable<K, V, S>> {
+        // The shard tables reduce the same hash to a bucket with its low
+        // bits, so pick the shard with the high ones.
+        let hash = hasher::hash_key(&self.hasher, key) >> (usize::BITS / 2);
+        // A thread that panics while holding the lock leaves the shard's
+        // table consistent, at worst with a half updated value, so keep
+        // using it.
+        self.shards[hash % self.shards.len()]
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Inserts `value` under `key`, returning the value it replaced.
+    ///
+    /// # Errors
+    /// Fails if the value can't be inserted.
+    pub fn insert(&self, key: &K, value: V) -> Result<Option<V>> {
+        self.shard(key).insert(key, value)
+    }
+
+    /// Runs `f` on the value of `key`, if it's in the table.
+    pub fn update<F, R>(&self, key: &K, f: F) -> Option<R>
+    where
+        F: FnOnce(&mut V) -> R,
+    {
+        self.shard(key).get_mut(key).map(f)
+    }
+
+    /// Runs `f` on the value of `key`, inserting `default()` first if the
+    /// key is missing. The shard stays locked in between, so concurrent
+    /// calls for the same key never insert twice.
+    ///
+    /// # Errors
+    /// Fails if the value can't be inserted.
+    pub fn update_or_insert_with<D, F, R>(&self, key: K, default: D, f: F) -> Result<R>
+    where
+        D: FnOnce() -> V,
+        F: FnOnce(&mut V) -> R,
+    {
+        let mut shard = self.shard(&key);
+        shard.entry(key).or_try_insert_with(default).map(f)
+    }
+
+    /// Returns a copy of the value of `key`.
+    #[must_use]
+    pub fn get_cloned(&self, key: &K) -> Option<V> {
+        self.shard(key).get(key).cloned()
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.shard(key).remove(key)
+    }
+}
+
+impl<K, V, S> ShardedTable<K, V, S> {
+    /// Number of items stored in the table. Other threads may change it
+    /// while the shards are being counted.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap_or_else(PoisonError::into_inner).len())
+            .sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<K, V, S> Default for ShardedTable<K, V, S>
+where
+    K: Clone + Default + Hashable + PartialEq,
+    V: Clone + Default,
+    S: BuildHasher + Default,
+{
+    fn default() -> Self {
+        let threads = thread::available_parallelism().map_or(1, NonZeroUsize::get);
+        Self::with_hasher(threads * SHARDS_PER_THREAD, S::default())
+    }
+}
+
+/// Owning iterator over a [`ShardedTable`], created by `into_iter`.
+pub struct IntoIter<K, V, S> {
+    shards: vec::IntoIter<Mutex<HashTable<K, V, S>>>,
+    items: hash_table::IntoIter<K, V>,
+    remaining: usize,
+}
+
+impl<K, V, S> Iterator for IntoIter<K, V, S> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.items.next() {
+                self.remaining -= 1;
+                return Some(item);
+            }
+            let shard = self.shards.next()?;
+            self.items = shard
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner)
+                .into_iter();
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S> ExactSizeIterator for IntoIter<K, V, S> {}
+
+impl<K, V, S> FusedIterator for IntoIter<K, V, S> {}
+
+impl<K, V, S> IntoIterator for ShardedTable<K, V, S> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let remaining = self.len();
+        IntoIter {
+            shards: self.shards.into_iter(),
+            items: hash_table::IntoIter::default(),
+            remaining,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_update_remove() {
+        let table = ShardedTable::new(4);
+        assert_eq!(table.insert(&1u32, 10u32).unwrap(), None);
+        assert_eq!(table.insert(&1, 11).unwrap(), Some(10));
+        assert_eq!(table.update(&1, |v| *v += 1), Some(()));
+        assert_eq!(table.update(&2, |v| *v += 1), None);
+
+        assert_eq!(table.get_cloned(&1), Some(12));
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.remove(&1), Some(12));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn parallel_updates() {
+        let table: ShardedTable<u32, Vec<u32>> = ShardedTable::default();
+        thread::scope(|s| {
+            for t in 0..8u32 {
+                let table = &table;
+                s.spawn(move || {
+                    for i in 0..1000u32 {
+                        table
+                            .update_or_insert_with(i % 100, Vec::new, |v| v.push(t))
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(table.len(), 100);
+        let mut items = table.into_iter().collect::<Vec<_>>();
+        assert_eq!(items.len(), 100);
+        items.sort_unstable();
+        for (i, (key, mut threads)) in (0..).zip(items) {
+            assert_eq!(key, i);
+            threads.sort_unstable();
+            let expected = (0..8).flat_map(|t| [t; 10]).collect::<Vec<_>>();
+            assert_eq!(threads, expected);
+        }
+    }
+
+    #[test]
+    fn single_shard() {
+        let table = ShardedTable::new(0);
+        for i in 0..100u32 {
+            table.insert(&i.to_string(), i).unwrap();
+        }
+        assert_eq!(table.shards.len(), 1);
+        assert_eq!(table.get_cloned(&"42".to_string()), Some(42));
+        assert_eq!(table.into_iter().map(|(_, v)| v).sum::<u32>(), 4950);
+    }
+}
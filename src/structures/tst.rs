@@ -1,9 +1,177 @@
-use core::fmt::Debug;
+use core::fmt::{self, Debug, Formatter};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
 
 // Ternary Search tree
-use anyhow::Result;
+use anyhow::{bail, Result};
+use unicode_normalization::UnicodeNormalization;
+
+/// Folds `word` into the form keys are stored and searched in: trimmed,
+/// lowercase and without accents, so "Thomas Müller" is found by typing
+/// "thomas muller".
+#[must_use]
+pub fn normalize(word: &str) -> String {
+    let mut key = String::with_capacity(word.len());
+    let word = word.trim().to_lowercase();
+    // Split accented letters into base letter + marks, drop the marks and
+    // put back together whatever else was split (Hangul syllables, kana
+    // with voicing marks).
+    for c in word.nfd().filter(|c| !is_diacritic(*c)).nfc() {
+        match c {
+            // Letters with no decomposition into a plain one.
+            'ß' => key.push_str("ss"),
+            'æ' => key.push_str("ae"),
+            'œ' => key.push_str("oe"),
+            'þ' => key.push_str("th"),
+            'ø' => key.push('o'),
+            'đ' | 'ð' => key.push('d'),
+            'ł' => key.push('l'),
+            'ı' => key.push('i'),
+            _ => key.push(c),
+        }
+    }
+    key
+}
+
+// Combining Diacritical Marks, the accents Latin letters decompose into.
+fn is_diacritic(c: char) -> bool {
+    ('\u{300}'..='\u{36f}').contains(&c)
+}
+
+// One piece of a glob pattern.
+#[derive(Debug, PartialEq)]
+enum Glob {
+    Char(char),
+    // `?`
+    Any,
+    // `*`
+    Star,
+    // `[a-z_]`, or `[!a-z_]` when negated.
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Glob {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Glob::Char(p) => *p == c,
+            Glob::Any | Glob::Star => true,
+            Glob::Class { negated, ranges } => {
+                ranges.iter().any(|(a, b)| (*a..=*b).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+fn parse_glob(pattern: &str) -> Result<Vec<Glob>> {
+    let mut glob = vec![];
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '?' => glob.push(Glob::Any),
+            // `**` matches the same as `*`.
+            '*' if glob.last() == Some(&Glob::Star) => {}
+            '*' => glob.push(Glob::Star),
+            '[' => {
+                let negated = chars.next_if(|c| *c == '!' || *c == '^').is_some();
+                let mut ranges = vec![];
+                loop {
+                    match chars.next() {
+                        None => bail!("unclosed character class in {pattern:?}"),
+                        Some(']') if ranges.is_empty() => {
+                            bail!("empty character class in {pattern:?}")
+                        }
+                        Some(']') => break,
+                        Some(a) => match chars.next_if_eq(&'-') {
+                            // A trailing `-` is a literal one.
+                            Some(_) if chars.peek() == Some(&']') => {
+                                ranges.push((a, a));
+                                ranges.push(('-', '-'));
+                            }
+                            Some(_) => match chars.next() {
+                                Some(b) if a <= b => ranges.push((a, b)),
+                                Some(b) => bail!("invalid range {a}-{b} in {pattern:?}"),
+                                None => bail!("unclosed character class in {pattern:?}"),
+                            },
+                            None => ranges.push((a, a)),
+                        },
+                    }
+                }
+                glob.push(Glob::Class { negated, ranges });
+            }
+            c => glob.push(Glob::Char(c)),
+        }
+    }
+    Ok(glob)
+}
+
+// Adds to `states` the positions reachable by letting the stars in `glob`
+// match nothing.
+fn skip_stars(glob: &[Glob], states: &mut Vec<usize>) {
+    let mut i = 0;
+    while i < states.len() {
+        let state = states[i];
+        if glob.get(state) == Some(&Glob::Star) && !states.contains(&(state + 1)) {
+            states.push(state + 1);
+        }
+        i += 1;
+    }
+}
+
+// Entry of the `find_top` queue: a key found, with its weight, or a subtree
+// whose keys weigh at most `weight`. Both hold the last letter of the path
+// leading to them, see `spell`.
+enum Top<'a, T: Default + Debug> {
+    Key(u64, Option<usize>, &'a T),
+    Tree(u64, Option<usize>, &'a TstNode<T>),
+}
+
+// Spells the key ending at `letters[last]`. Every letter `find_top` walks
+// past is stored once along with the index of the letter before it, so
+// keys are only built for the entries returned.
+fn spell(letters: &[(Option<usize>, char)], mut last: Option<usize>) -> String {
+    let mut key = vec![];
+    while let Some(i) = last {
+        let (before, c) = letters[i];
+        key.push(c);
+        last = before;
+    }
+    key.iter().rev().collect()
+}
+
+impl<T: Default + Debug> Top<'_, T> {
+    // Keys come out before subtrees of the same weight, so the search stops
+    // as soon as it has enough.
+    fn rank(&self) -> (u64, bool) {
+        match self {
+            Top::Key(weight, ..) => (*weight, true),
+            Top::Tree(weight, ..) => (*weight, false),
+        }
+    }
+}
+
+impl<T: Default + Debug> PartialEq for Top<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank() == other.rank()
+    }
+}
+
+impl<T: Default + Debug> Eq for Top<'_, T> {}
+
+impl<T: Default + Debug> PartialOrd for Top<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T: Default + Debug> Ord for Top<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank().cmp(&other.rank())
+    }
+}
 
-#[derive(Debug, Clone)]
 struct TstNode<T>
 where
     T: Default + Debug,
@@ -13,9 +181,13 @@ where
     next: Option<Box<TstNode<T>>>,
     content: Option<T>,
     c: char,
+    // Weight of the key ending here, used to rank completions.
+    weight: u64,
+    // Heaviest key in this subtree, `esq` and `dir` included.
+    best: u64,
 }
 
-#[derive(Debug, Default, Clone)]
+#[derive(Default, Clone)]
 pub struct Tst<T>
 where
     T: Default + Debug + Clone,
@@ -23,184 +195,453 @@ where
     root: TstNode<T>,
 }
 
+// Which way a search goes from a node: to one of its children, or nowhere
+// because the node ends the word looked up.
+#[derive(Clone, Copy, PartialEq)]
+enum Step {
+    Esq,
+    Dir,
+    Next,
+    End,
+}
+
 impl<T> TstNode<T>
 where
     T: Default + Debug + Clone,
 {
+    // Where the search for `word[i]` goes from this node.
+    fn step(&self, word: &[char], i: usize) -> Step {
+        match word[i].cmp(&self.c) {
+            Ordering::Less => Step::Esq,
+            Ordering::Greater => Step::Dir,
+            Ordering::Equal if i + 1 == word.len() => Step::End,
+            Ordering::Equal => Step::Next,
+        }
+    }
+
+    fn child(&self, step: Step) -> Option<&TstNode<T>> {
+        match step {
+            Step::Esq => self.esq.as_deref(),
+            Step::Dir => self.dir.as_deref(),
+            Step::Next => self.next.as_deref(),
+            Step::End => None,
+        }
+    }
+
     pub fn insert(&mut self, word: &str, content: T) -> Result<()> {
-        let word = word.to_lowercase();
-        self._insert(&word, content, 0)?;
-        Ok(())
-    }
-
-    fn _insert(&mut self, word: &String, content: T, scanned: usize) -> Result<()> {
-        let last = scanned == word.len() - 1;
-
-        if let Some(c) = word.chars().nth(scanned as usize) {
-            if self.c == c {
-                if last {
-                    self.content = Some(content);
-                } else if let Some(next) = &mut self.next {
-                    next._insert(word, content, scanned + 1)?;
-                } else {
-                    let mut next = TstNode::default();
-                    if let Some(c) = word.chars().nth(scanned as usize + 1) {
-                        next.c = c;
-                    }
-                    self.next = Some(Box::new(next));
-                    self.next
-                        .as_mut()
-                        .unwrap()
-                        ._insert(word, content, scanned + 1)?;
-                }
-            } else if c < self.c {
-                if let Some(a) = &mut self.esq {
-                    a._insert(word, content, scanned)?;
-                } else {
-                    self.esq = Some(Box::new(TstNode::<T> {
-                        c,
-                        content: if last { Some(content.clone()) } else { None },
-                        ..Default::default()
-                    }));
-                    if !last {
-                        self.esq.as_mut().unwrap()._insert(word, content, scanned)?;
-                    }
+        let word = normalize(word).chars().collect::<Vec<char>>();
+        if word.is_empty() {
+            bail!("can't insert an empty key");
+        }
+        let mut node = self;
+        let mut i = 0;
+        loop {
+            let child = match node.step(&word, i) {
+                Step::Esq => &mut node.esq,
+                Step::Dir => &mut node.dir,
+                Step::Next => {
+                    i += 1;
+                    &mut node.next
                 }
-            } else if let Some(a) = &mut self.dir {
-                a._insert(word, content, scanned)?;
-            } else {
-                self.dir = Some(Box::new(TstNode::<T> {
-                    c,
-                    content: if last { Some(content.clone()) } else { None },
-                    ..Default::default()
-                }));
-                if !last {
-                    self.dir.as_mut().unwrap()._insert(word, content, scanned)?;
+                Step::End => {
+                    node.content = Some(content);
+                    return Ok(());
                 }
-            }
+            };
+            node = child.get_or_insert_with(|| {
+                let mut node = Box::<TstNode<T>>::default();
+                node.c = word[i];
+                node
+            });
         }
+    }
 
-        Ok(())
+    // Node where `word` ends, if the tree has a key starting with it.
+    fn _find_node(&self, word: &[char]) -> Option<&TstNode<T>> {
+        if word.is_empty() {
+            return None;
+        }
+        let mut node = self;
+        let mut i = 0;
+        loop {
+            let step = node.step(word, i);
+            match step {
+                Step::End => return Some(node),
+                Step::Next => i += 1,
+                Step::Esq | Step::Dir => {}
+            }
+            node = node.child(step)?;
+        }
     }
 
     pub fn get(&self, word: String) -> Option<T> {
-        let word = Self::pre_process(word);
-        self._get(word, 0)
-    }
-
-    fn _get(&self, word: String, scanned: usize) -> Option<T> {
-        let last = scanned == word.len() - 1;
-        if let Some(c) = word.chars().nth(scanned as usize) {
-            if self.c == c {
-                if last {
-                    self.content.clone()
-                } else if let Some(next) = &self.next {
-                    next._get(word, scanned + 1)
-                } else {
-                    None
-                }
-            } else if c < self.c {
-                if let Some(a) = &self.esq {
-                    a._get(word, scanned)
-                } else {
-                    None
+        let word = Self::pre_process(word).chars().collect::<Vec<char>>();
+        self._find_node(&word)?.content.clone()
+    }
+
+    // Depth-first walk over this node and its `next`, `esq` and `dir`
+    // subtrees, in that order. `visit` gets every node along with the
+    // letters leading to it, starting with `prefix`, and returns whether to
+    // go on into its `next` subtree.
+    fn _walk<'a, F>(&'a self, prefix: &[char], mut visit: F)
+    where
+        F: FnMut(&'a TstNode<T>, &[char]) -> bool,
+    {
+        let mut path = prefix.to_vec();
+        let mut stack = vec![(self, path.len())];
+        while let Some((node, depth)) = stack.pop() {
+            // Whatever was visited since this node was stacked sits deeper
+            // in the tree, so `path` still starts with its letters.
+            path.truncate(depth);
+            if let Some(dir) = &node.dir {
+                stack.push((dir, depth));
+            }
+            if let Some(esq) = &node.esq {
+                stack.push((esq, depth));
+            }
+            if visit(node, &path) {
+                if let Some(next) = &node.next {
+                    path.push(node.c);
+                    stack.push((next, depth + 1));
                 }
-            } else if let Some(a) = &self.dir {
-                a._get(word, scanned)
-            } else {
-                None
             }
-        } else {
-            None
         }
     }
 
-    pub fn find_from_prefix(&self, prefix: String) -> Vec<(String, T)> {
-        let prefix = Self::pre_process(prefix);
-        self._find_from_prefix(prefix, 0)
+    fn key(path: &[char], c: char) -> String {
+        path.iter().chain([&c]).collect()
     }
 
-    fn _get_child_words(&self, prefix: String) -> Vec<(String, T)> {
+    pub fn find_from_prefix(&self, prefix: String) -> Vec<(String, T)> {
+        let prefix = Self::pre_process(prefix).chars().collect::<Vec<char>>();
+        if prefix.is_empty() {
+            return self.get_words();
+        }
         let mut words = vec![];
-        if let Some(content) = &self.content {
-            words.push((prefix.clone() + &self.c.to_string(), content.clone()));
+        let Some(node) = self._find_node(&prefix) else {
+            return words;
+        };
+        if let Some(content) = &node.content {
+            words.push((prefix.iter().collect(), content.clone()));
         }
-        if let Some(next) = &self.next {
-            words.append(&mut next._get_words(prefix));
+        if let Some(next) = &node.next {
+            next._walk(&prefix, |node, path| {
+                if let Some(content) = &node.content {
+                    words.push((Self::key(path, node.c), content.clone()));
+                }
+                true
+            });
         }
         words
     }
 
     pub fn get_words(&self) -> Vec<(String, T)> {
-        self._get_words("".to_string())
+        let mut words = vec![];
+        self._walk(&[], |node, path| {
+            if let Some(content) = &node.content {
+                words.push((Self::key(path, node.c), content.clone()));
+            }
+            true
+        });
+        words
     }
 
-    fn _get_words(&self, prefix: String) -> Vec<(String, T)> {
-        let mut words = vec![];
-        if let Some(content) = &self.content {
-            words.push((prefix.clone() + &self.c.to_string(), content.clone()));
-        }
-        if let Some(next) = &self.next {
-            words.append(&mut next._get_words(prefix.clone() + &self.c.to_string()));
-        }
+    pub fn find_similar(&self, word: String, max: usize) -> Vec<(String, T, usize)> {
+        let word = Self::pre_process(word).chars().collect::<Vec<char>>();
+        // `rows[d]` holds the edit distances from the first `d` letters of
+        // the path walked to every prefix of `word`. The row two back lets
+        // two swapped letters count as a single edit.
+        let mut rows = vec![(0..=word.len()).collect::<Vec<usize>>()];
+        let mut found = vec![];
+        self._walk(&[], |node, path| {
+            let depth = path.len();
+            rows.truncate(depth + 1);
+            let row = &rows[depth];
+            let mut current = Vec::with_capacity(row.len());
+            current.push(row[0] + 1);
+            for j in 1..row.len() {
+                let cost = usize::from(word[j - 1] != node.c);
+                let mut distance = (row[j] + 1).min(current[j - 1] + 1).min(row[j - 1] + cost);
+                if j > 1 && path.last() == Some(&word[j - 1]) && word[j - 2] == node.c {
+                    distance = distance.min(rows[depth - 1][j - 2] + 1);
+                }
+                current.push(distance);
+            }
+
+            if let Some(content) = &node.content {
+                if current[word.len()] <= max {
+                    let key = Self::key(path, node.c);
+                    found.push((key, content.clone(), current[word.len()]));
+                }
+            }
+            // Distances only grow further down, so stop once every prefix
+            // of the word is too far.
+            let descend = current.iter().any(|d| *d <= max);
+            rows.push(current);
+            descend
+        });
+        found
+    }
 
-        if let Some(esq) = &self.esq {
-            words.append(&mut esq._get_words(prefix.clone()));
+    pub fn find_glob(&self, pattern: String) -> Result<Vec<(String, T)>> {
+        let pattern = Self::pre_process(pattern);
+        let glob = parse_glob(&pattern)?;
+        // `states[d]` are the positions in `glob` the keys continuing the
+        // first `d` letters of the path walked can match from.
+        let mut states = vec![vec![0]];
+        skip_stars(&glob, &mut states[0]);
+        let mut found = vec![];
+        self._walk(&[], |node, path| {
+            let depth = path.len();
+            states.truncate(depth + 1);
+            let mut next_states = vec![];
+            for &state in &states[depth] {
+                match glob.get(state) {
+                    Some(Glob::Star) => next_states.push(state),
+                    Some(piece) if piece.matches(node.c) => next_states.push(state + 1),
+                    _ => {}
+                }
+            }
+            next_states.sort_unstable();
+            next_states.dedup();
+            skip_stars(&glob, &mut next_states);
+
+            if let Some(content) = &node.content {
+                if next_states.contains(&glob.len()) {
+                    found.push((Self::key(path, node.c), content.clone()));
+                }
+            }
+            let descend = !next_states.is_empty();
+            states.push(next_states);
+            descend
+        });
+        Ok(found)
+    }
+
+    fn _print_vertical(&self) {
+        // Nodes are printed after their `next` and `dir` subtrees and before
+        // their `esq` one, so a node is stacked again once its children are.
+        let mut stack = vec![(self, 0, false)];
+        while let Some((node, level, children_stacked)) = stack.pop() {
+            if children_stacked {
+                println!("{}{} - {:?}", ".".repeat(level), node.c, node.content);
+                continue;
+            }
+            if let Some(esq) = &node.esq {
+                stack.push((esq, level + 1, false));
+            }
+            stack.push((node, level, true));
+            if let Some(dir) = &node.dir {
+                stack.push((dir, level + 1, false));
+            }
+            if let Some(next) = &node.next {
+                stack.push((next, level + 1, false));
+            }
         }
+    }
+
+    pub fn get_weight(&self, word: String) -> Option<u64> {
+        let word = Self::pre_process(word).chars().collect::<Vec<char>>();
+        self._find_node(&word)
+            .filter(|node| node.content.is_some())
+            .map(|node| node.weight)
+    }
 
-        if let Some(dir) = &self.dir {
-            words.append(&mut dir._get_words(prefix));
+    pub fn set_weight(&mut self, word: String, weight: u64) -> bool {
+        let word = Self::pre_process(word).chars().collect::<Vec<char>>();
+        if word.is_empty() {
+            return false;
+        }
+        // First find, for every node on the way to `word`, the heaviest key
+        // hanging off it outside that way. The heaviest key under a node is
+        // then the heaviest among it and the nodes past it.
+        let mut bests = vec![];
+        let mut node = &*self;
+        let mut i = 0;
+        loop {
+            let step = node.step(&word, i);
+            let own = match step {
+                Step::End if node.content.is_none() => return false,
+                Step::End => weight,
+                _ if node.content.is_some() => node.weight,
+                _ => 0,
+            };
+            let best = [Step::Esq, Step::Dir, Step::Next]
+                .into_iter()
+                .filter(|other| *other != step)
+                .filter_map(|other| node.child(other))
+                .map(|child| child.best)
+                .fold(own, u64::max);
+            bests.push(best);
+            if step == Step::End {
+                break;
+            }
+            if step == Step::Next {
+                i += 1;
+            }
+            let Some(child) = node.child(step) else {
+                return false;
+            };
+            node = child;
+        }
+        for j in (1..bests.len()).rev() {
+            bests[j - 1] = bests[j - 1].max(bests[j]);
         }
 
-        words
+        let mut node = self;
+        let mut i = 0;
+        for best in bests {
+            node.best = best;
+            let child = match node.step(&word, i) {
+                Step::Esq => &mut node.esq,
+                Step::Dir => &mut node.dir,
+                Step::Next => {
+                    i += 1;
+                    &mut node.next
+                }
+                Step::End => {
+                    node.weight = weight;
+                    break;
+                }
+            };
+            node = child.as_deref_mut().unwrap();
+        }
+        true
     }
 
-    fn _find_from_prefix(&self, prefix: String, scanned: usize) -> Vec<(String, T)> {
-        let last = scanned == prefix.len() - 1;
-        if let Some(c) = prefix.chars().nth(scanned as usize) {
-            if self.c == c {
-                if last {
-                    self._get_child_words(prefix)
-                } else if let Some(next) = &self.next {
-                    next._find_from_prefix(prefix, scanned + 1)
-                } else {
-                    vec![]
+    // Best-first walk: subtrees are opened heaviest first, and only while
+    // they may still hold one of the `k` heaviest keys.
+    pub fn find_top(&self, prefix: String, k: usize) -> Vec<(String, T, u64)> {
+        let prefix = Self::pre_process(prefix).chars().collect::<Vec<char>>();
+        let mut letters = vec![];
+        let mut queue = BinaryHeap::new();
+        if prefix.is_empty() {
+            queue.push(Top::Tree(self.best, None, self));
+        } else if let Some(node) = self._find_node(&prefix) {
+            for (i, c) in prefix.iter().enumerate() {
+                letters.push((i.checked_sub(1), *c));
+            }
+            let last = letters.len().checked_sub(1);
+            if let Some(content) = &node.content {
+                queue.push(Top::Key(node.weight, last, content));
+            }
+            if let Some(next) = &node.next {
+                queue.push(Top::Tree(next.best, last, next));
+            }
+        }
+
+        let mut top = vec![];
+        while top.len() < k {
+            match queue.pop() {
+                None => break,
+                Some(Top::Key(weight, last, content)) => {
+                    top.push((spell(&letters, last), content.clone(), weight));
                 }
-            } else if c < self.c {
-                if let Some(a) = &self.esq {
-                    a._find_from_prefix(prefix, scanned)
-                } else {
-                    vec![]
+                Some(Top::Tree(_, prefix, node)) => {
+                    letters.push((prefix, node.c));
+                    let key = Some(letters.len() - 1);
+                    if let Some(content) = &node.content {
+                        queue.push(Top::Key(node.weight, key, content));
+                    }
+                    if let Some(next) = &node.next {
+                        queue.push(Top::Tree(next.best, key, next));
+                    }
+                    if let Some(esq) = &node.esq {
+                        queue.push(Top::Tree(esq.best, prefix, esq));
+                    }
+                    if let Some(dir) = &node.dir {
+                        queue.push(Top::Tree(dir.best, prefix, dir));
+                    }
                 }
-            } else if let Some(a) = &self.dir {
-                a._find_from_prefix(prefix, scanned)
-            } else {
-                vec![]
             }
-        } else {
-            vec![]
         }
+        top
     }
 
-    fn _print_vertical(&self, level: usize) {
-        if let Some(next) = &self.next {
-            next._print_vertical(level + 1);
-        }
-        if let Some(dir) = &self.dir {
-            dir._print_vertical(level + 1);
+    fn pre_process(word: String) -> String {
+        normalize(&word)
+    }
+}
+
+// The derived drop would recurse once per letter of the longest key, so
+// children are unlinked and dropped one at a time instead.
+impl<T> Drop for TstNode<T>
+where
+    T: Default + Debug,
+{
+    fn drop(&mut self) {
+        let mut stack = vec![];
+        stack.extend(self.esq.take());
+        stack.extend(self.dir.take());
+        stack.extend(self.next.take());
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.esq.take());
+            stack.extend(node.dir.take());
+            stack.extend(node.next.take());
         }
-        for _ in 0..level {
-            print!(".");
+    }
+}
+
+// Derived clone and debug would also recurse once per letter, so children
+// are copied through a stack and only the node itself is printed.
+impl<T> Clone for TstNode<T>
+where
+    T: Default + Debug + Clone,
+{
+    fn clone(&self) -> Self {
+        let mut root = self.clone_node();
+        let mut stack = vec![(self, &mut root)];
+        while let Some((node, copy)) = stack.pop() {
+            let TstNode { esq, dir, next, .. } = copy;
+            for (child, copy) in [(&node.esq, esq), (&node.dir, dir), (&node.next, next)] {
+                if let Some(child) = child {
+                    stack.push((child, copy.insert(Box::new(child.clone_node()))));
+                }
+            }
         }
-        println!("{} - {:?}", self.c, self.content);
-        if let Some(esq) = &self.esq {
-            esq._print_vertical(level + 1);
+        root
+    }
+}
+
+impl<T> TstNode<T>
+where
+    T: Default + Debug + Clone,
+{
+    // This node alone, without its children.
+    fn clone_node(&self) -> Self {
+        TstNode {
+            esq: None,
+            dir: None,
+            next: None,
+            content: self.content.clone(),
+            c: self.c,
+            weight: self.weight,
+            best: self.best,
         }
     }
+}
 
-    fn pre_process(word: String) -> String {
-        let word = word.to_lowercase().trim().to_string();
-        word
+impl<T> Debug for TstNode<T>
+where
+    T: Default + Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TstNode")
+            .field("c", &self.c)
+            .field("content", &self.content)
+            .field("weight", &self.weight)
+            .field("best", &self.best)
+            .finish_non_exhaustive()
+    }
+}
+
+// Lists the keys with their content, like a map.
+impl<T> Debug for Tst<T>
+where
+    T: Default + Debug + Clone,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_map().entries(self.get_words()).finish()
     }
 }
 
@@ -215,6 +656,8 @@ where
             next: None,
             esq: None,
             dir: None,
+            weight: 0,
+            best: 0,
         }
     }
 }
@@ -229,6 +672,10 @@ where
         }
     }
 
+    /// Stores `content` under `word`, replacing what was there.
+    ///
+    /// # Errors
+    /// Fails if `word` is empty once normalized.
     pub fn insert(&mut self, word: &str, content: T) -> Result<()> {
         self.root.insert(word, content)
     }
@@ -245,8 +692,44 @@ where
         self.root.get_words()
     }
 
+    /// Returns the keys at most `max` edits away from `word`, with their
+    /// distance. An edit inserts, removes or replaces a letter, or swaps two
+    /// adjacent ones.
+    pub fn find_similar(&self, word: String, max: usize) -> Vec<(String, T, usize)> {
+        self.root.find_similar(word, max)
+    }
+
+    /// Returns the keys matching the glob `pattern`: `?` stands for any
+    /// letter, `*` for any run of letters and `[...]` for one of the letters
+    /// or ranges listed, or one not listed when it starts with `!` or `^`.
+    ///
+    /// # Errors
+    /// Fails if a character class isn't closed, is empty or has a backwards
+    /// range.
+    pub fn find_glob(&self, pattern: String) -> Result<Vec<(String, T)>> {
+        self.root.find_glob(pattern)
+    }
+
+    /// Weight of the key `word`, zero until set with [`Tst::set_weight`].
+    pub fn get_weight(&self, word: String) -> Option<u64> {
+        self.root.get_weight(word)
+    }
+
+    /// Sets the weight [`Tst::find_top`] ranks the key `word` by. Returns
+    /// false if `word` isn't in the tree.
+    pub fn set_weight(&mut self, word: String, weight: u64) -> bool {
+        self.root.set_weight(word, weight)
+    }
+
+    /// Returns the `k` heaviest keys starting with `prefix`, heaviest first,
+    /// with their weight. Only the subtrees that can hold one of them are
+    /// visited.
+    pub fn find_top(&self, prefix: String, k: usize) -> Vec<(String, T, u64)> {
+        self.root.find_top(prefix, k)
+    }
+
     pub fn print_vertical(&self) {
-        self.root._print_vertical(0);
+        self.root._print_vertical();
     }
 }
 
@@ -264,7 +747,7 @@ mod tests {
         tst.insert(&String::from("ter"), 2).unwrap();
         tst.insert(&String::from("terroso"), 2).unwrap();
         tst.insert(&String::from("Voar"), 2).unwrap();
-        // tst._print_vertical(0);
+        // tst._print_vertical();
     }
 
     #[test]
@@ -390,4 +873,408 @@ mod tests {
             vec![("bola".to_string(), 1), ("bolo".to_string(), 5)]
         );
     }
+
+    #[test]
+    fn normalize_keys() {
+        assert_eq!(normalize("  Thomas Müller "), "thomas muller");
+        assert_eq!(normalize("Sergio Agüero"), "sergio aguero");
+        assert_eq!(normalize("Martin Ødegaard"), "martin odegaard");
+        assert_eq!(normalize("Łukasz Piszczek"), "lukasz piszczek");
+        assert_eq!(normalize("Çağlar Söyüncü"), "caglar soyuncu");
+        assert_eq!(normalize("Mario Götze"), normalize("Mario Go\u{308}tze"));
+        // Voicing marks are part of kana, not accents.
+        assert_eq!(normalize("ガ"), "ガ");
+        assert_eq!(normalize("손흥민"), "손흥민");
+    }
+
+    #[test]
+    fn accent_insensitive() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("Thomas Müller", 1).unwrap();
+        tst.insert("Sergio Agüero", 2).unwrap();
+        tst.insert("Kylian Mbappé", 3).unwrap();
+
+        assert_eq!(tst.get(String::from("thomas muller")), Some(1));
+        assert_eq!(tst.get(String::from("THOMAS MÜLLER")), Some(1));
+        assert_eq!(tst.get(String::from("sergio aguero")), Some(2));
+        assert_eq!(
+            tst.find_from_prefix(String::from("kylian mbap")),
+            vec![("kylian mbappe".to_string(), 3)]
+        );
+    }
+
+    // Edit distance counting adjacent swaps, straight from the definition.
+    fn distance(a: &str, b: &str) -> usize {
+        let a = a.chars().collect::<Vec<char>>();
+        let b = b.chars().collect::<Vec<char>>();
+        let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+        for (i, row) in d.iter_mut().enumerate() {
+            row[0] = i;
+        }
+        for (j, cell) in d[0].iter_mut().enumerate() {
+            *cell = j;
+        }
+        for i in 1..=a.len() {
+            for j in 1..=b.len() {
+                let cost = usize::from(a[i - 1] != b[j - 1]);
+                d[i][j] = (d[i - 1][j] + 1)
+                    .min(d[i][j - 1] + 1)
+                    .min(d[i - 1][j - 1] + cost);
+                if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                    d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+                }
+            }
+        }
+        d[a.len()][b.len()]
+    }
+
+    #[test]
+    fn find_similar() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("Cristiano Ronaldo", 1).unwrap();
+        tst.insert("Ronaldo", 2).unwrap();
+        tst.insert("Lionel Messi", 3).unwrap();
+
+        assert_eq!(
+            tst.find_similar(String::from("Cristano Ronaldo"), 2),
+            vec![("cristiano ronaldo".to_string(), 1, 1)]
+        );
+        assert_eq!(
+            tst.find_similar(String::from("lionel mesis"), 1),
+            vec![("lionel messi".to_string(), 3, 1)]
+        );
+        assert_eq!(
+            tst.find_similar(String::from("ronaldo"), 0),
+            vec![("ronaldo".to_string(), 2, 0)]
+        );
+        assert!(tst.find_similar(String::from("rnoaldo"), 0).is_empty());
+        assert!(tst.find_similar(String::from("neymar"), 2).is_empty());
+    }
+
+    #[test]
+    fn find_similar_matches_distance() {
+        let words = [
+            "bola", "bolo", "bala", "hora", "horas", "terra", "ter", "terroso", "voar", "ovar",
+            "b", "ab", "ba", "abc", "acb", "messi", "mesi", "messias",
+        ];
+        let mut tst = Tst::<usize>::new();
+        for (i, word) in words.iter().enumerate() {
+            tst.insert(word, i).unwrap();
+        }
+
+        for query in [
+            "bola", "obla", "ter", "a", "ba", "voar", "msesi", "horsa", "xyz",
+        ] {
+            for max in 0..4 {
+                let mut found = tst.find_similar(query.to_string(), max);
+                found.sort_unstable();
+                let mut expected = words
+                    .iter()
+                    .enumerate()
+                    .map(|(i, word)| (word.to_string(), i, distance(query, word)))
+                    .filter(|(_, _, d)| *d <= max)
+                    .collect::<Vec<(String, usize, usize)>>();
+                expected.sort_unstable();
+                assert_eq!(found, expected, "{query} {max}");
+            }
+        }
+    }
+
+    #[test]
+    fn find_glob() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("Ronaldinho", 1).unwrap();
+        tst.insert("Robinho", 2).unwrap();
+        tst.insert("De Bruyne", 3).unwrap();
+        tst.insert("De Gea", 4).unwrap();
+        tst.insert("Dede", 5).unwrap();
+
+        let mut found = tst.find_glob(String::from("*inho")).unwrap();
+        found.sort_unstable();
+        assert_eq!(
+            found,
+            vec![("robinho".to_string(), 2), ("ronaldinho".to_string(), 1)]
+        );
+        let mut found = tst.find_glob(String::from("De *")).unwrap();
+        found.sort_unstable();
+        assert_eq!(
+            found,
+            vec![("de bruyne".to_string(), 3), ("de gea".to_string(), 4)]
+        );
+        assert_eq!(
+            tst.find_glob(String::from("d?d?")).unwrap(),
+            vec![("dede".to_string(), 5)]
+        );
+        assert_eq!(
+            tst.find_glob(String::from("ro[a-c]*")).unwrap(),
+            vec![("robinho".to_string(), 2)]
+        );
+        assert_eq!(
+            tst.find_glob(String::from("ro[!a-c]*")).unwrap(),
+            vec![("ronaldinho".to_string(), 1)]
+        );
+        assert!(tst.find_glob(String::from("robinh")).unwrap().is_empty());
+
+        assert!(tst.find_glob(String::from("ro[a-c")).is_err());
+        assert!(tst.find_glob(String::from("ro[]")).is_err());
+        assert!(tst.find_glob(String::from("ro[c-a]")).is_err());
+    }
+
+    // Glob matching by backtracking, to check the tree walk against.
+    fn glob_matches(glob: &[Glob], word: &[char]) -> bool {
+        match glob.split_first() {
+            None => word.is_empty(),
+            Some((Glob::Star, rest)) => (0..=word.len()).any(|i| glob_matches(rest, &word[i..])),
+            Some((piece, rest)) => match word.split_first() {
+                Some((c, word)) => piece.matches(*c) && glob_matches(rest, word),
+                None => false,
+            },
+        }
+    }
+
+    #[test]
+    fn find_glob_matches_backtracking() {
+        let words = [
+            "bola", "bolo", "bala", "hora", "horas", "terra", "ter", "terroso", "voar", "ovar",
+            "b", "ab", "ba", "abc", "acb", "a-b", "aab", "abab",
+        ];
+        let mut tst = Tst::<usize>::new();
+        for (i, word) in words.iter().enumerate() {
+            tst.insert(word, i).unwrap();
+        }
+
+        for pattern in [
+            "*",
+            "?",
+            "b*",
+            "*a",
+            "*a*",
+            "?o?a",
+            "ter*",
+            "*r*s*",
+            "[ab]*",
+            "[!ab]*",
+            "a[-]b",
+            "a[b-]*",
+            "*ab",
+            "**b",
+            "a*b",
+            "[a-c][a-c]",
+            "x*",
+        ] {
+            let mut found = tst.find_glob(pattern.to_string()).unwrap();
+            found.sort_unstable();
+            let glob = parse_glob(pattern).unwrap();
+            let mut expected = words
+                .iter()
+                .enumerate()
+                .filter(|(_, word)| glob_matches(&glob, &word.chars().collect::<Vec<char>>()))
+                .map(|(i, word)| (word.to_string(), i))
+                .collect::<Vec<(String, usize)>>();
+            expected.sort_unstable();
+            assert_eq!(found, expected, "{pattern}");
+        }
+    }
+
+    #[test]
+    fn find_top() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("Messi", 1).unwrap();
+        tst.insert("Mesut Özil", 2).unwrap();
+        tst.insert("Mertens", 3).unwrap();
+        tst.insert("Modric", 4).unwrap();
+        tst.insert("Neymar", 5).unwrap();
+
+        assert!(tst.set_weight(String::from("messi"), 90));
+        assert!(tst.set_weight(String::from("Mesut Ozil"), 40));
+        assert!(tst.set_weight(String::from("mertens"), 10));
+        assert!(tst.set_weight(String::from("modric"), 70));
+        assert!(tst.set_weight(String::from("neymar"), 80));
+        assert!(!tst.set_weight(String::from("mes"), 5));
+        assert!(!tst.set_weight(String::from("pele"), 5));
+        assert_eq!(tst.get_weight(String::from("MODRIC")), Some(70));
+        assert_eq!(tst.get_weight(String::from("mod")), None);
+
+        assert_eq!(
+            tst.find_top(String::from("m"), 2),
+            vec![("messi".to_string(), 1, 90), ("modric".to_string(), 4, 70)]
+        );
+        assert_eq!(
+            tst.find_top(String::from("me"), 5),
+            vec![
+                ("messi".to_string(), 1, 90),
+                ("mesut ozil".to_string(), 2, 40),
+                ("mertens".to_string(), 3, 10),
+            ]
+        );
+        assert_eq!(
+            tst.find_top(String::new(), 2),
+            vec![("messi".to_string(), 1, 90), ("neymar".to_string(), 5, 80)]
+        );
+        assert_eq!(tst.find_top(String::from("messi"), 1)[0].2, 90);
+        assert!(tst.find_top(String::from("x"), 3).is_empty());
+
+        // Lowering a weight moves the key down.
+        assert!(tst.set_weight(String::from("messi"), 0));
+        assert_eq!(
+            tst.find_top(String::from("m"), 1),
+            vec![("modric".to_string(), 4, 70)]
+        );
+    }
+
+    #[test]
+    fn find_top_matches_sort() {
+        let words = [
+            "bola", "bolo", "bala", "hora", "horas", "terra", "ter", "terroso", "voar", "ovar",
+            "b", "ab", "ba", "abc", "acb", "aab", "abab",
+        ];
+        let mut tst = Tst::<usize>::new();
+        for (i, word) in words.iter().enumerate() {
+            tst.insert(word, i).unwrap();
+        }
+        // Distinct weights, so the expected order is unique.
+        let weight = |i: usize| u64::try_from((i * 7) % words.len()).unwrap();
+        for (i, word) in words.iter().enumerate() {
+            assert!(tst.set_weight(word.to_string(), weight(i)));
+        }
+
+        for prefix in ["", "b", "bo", "ter", "a", "ab", "h", "x"] {
+            for k in [0, 1, 3, 20] {
+                let mut expected = words
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, word)| word.starts_with(prefix))
+                    .map(|(i, word)| (word.to_string(), i, weight(i)))
+                    .collect::<Vec<(String, usize, u64)>>();
+                expected.sort_unstable_by_key(|(_, _, weight)| std::cmp::Reverse(*weight));
+                expected.truncate(k);
+                assert_eq!(
+                    tst.find_top(prefix.to_string(), k),
+                    expected,
+                    "{prefix} {k}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn multibyte_keys() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("Son Heung-min", 1).unwrap();
+        tst.insert("손흥민", 2).unwrap();
+        tst.insert("本田圭佑", 3).unwrap();
+        tst.insert("本田拓也", 4).unwrap();
+        tst.insert("武磊", 5).unwrap();
+        tst.insert("武", 6).unwrap();
+        tst.insert("Игорь Акинфеев", 7).unwrap();
+        tst.insert("Kōki Ogawa", 8).unwrap();
+
+        assert_eq!(tst.get(String::from("손흥민")), Some(2));
+        assert_eq!(tst.get(String::from("손흥")), None);
+        assert_eq!(tst.get(String::from("本田圭佑")), Some(3));
+        assert_eq!(tst.get(String::from("武")), Some(6));
+        assert_eq!(tst.get(String::from("игорь акинфеев")), Some(7));
+        assert_eq!(tst.get(String::from("koki ogawa")), Some(8));
+
+        assert_eq!(
+            tst.find_from_prefix(String::from("本田")),
+            vec![("本田圭佑".to_string(), 3), ("本田拓也".to_string(), 4)]
+        );
+        assert_eq!(
+            tst.find_from_prefix(String::from("武")),
+            vec![("武".to_string(), 6), ("武磊".to_string(), 5)]
+        );
+        assert_eq!(
+            tst.find_from_prefix(String::from("Игорь")),
+            vec![("игорь акинфеев".to_string(), 7)]
+        );
+        assert_eq!(
+            tst.find_similar(String::from("本田圭"), 1),
+            vec![("本田圭佑".to_string(), 3, 1)]
+        );
+        assert_eq!(tst.find_glob(String::from("本田??")).unwrap().len(), 2);
+        assert_eq!(tst.get_words().len(), 8);
+    }
+
+    #[test]
+    fn prefix_is_a_key() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("ter", 1).unwrap();
+        tst.insert("terra", 2).unwrap();
+        tst.insert("tu", 3).unwrap();
+
+        assert_eq!(
+            tst.find_from_prefix(String::from("ter")),
+            vec![("ter".to_string(), 1), ("terra".to_string(), 2)]
+        );
+        assert_eq!(tst.find_from_prefix(String::new()).len(), 3);
+    }
+
+    #[test]
+    fn empty_key() {
+        let mut tst = Tst::<i32>::new();
+        assert!(tst.insert("  ", 1).is_err());
+        assert_eq!(tst.get(String::new()), None);
+        assert!(!tst.set_weight(String::new(), 1));
+        assert!(tst.get_words().is_empty());
+    }
+
+    #[test]
+    fn long_keys() {
+        // Deep enough to overflow the stack if any walk, or dropping the
+        // tree, recursed once per letter.
+        let long = "ab".repeat(100_000);
+        let mut tst = Tst::<i32>::new();
+        tst.insert(&long, 1).unwrap();
+        tst.insert(&(long.clone() + "c"), 2).unwrap();
+        for (i, c) in ('a'..='z').enumerate() {
+            tst.insert(&c.to_string(), i32::try_from(i).unwrap())
+                .unwrap();
+        }
+
+        assert_eq!(tst.get(long.clone()), Some(1));
+        assert_eq!(tst.find_from_prefix(String::from("abab")).len(), 2);
+        assert_eq!(tst.get_words().len(), 28);
+        assert!(tst.set_weight(long.clone() + "c", 5));
+        assert_eq!(tst.get_weight(long.clone()), Some(0));
+        assert_eq!(tst.find_top(String::from("a"), 1)[0].2, 5);
+        assert_eq!(tst.find_glob(String::from("*c")).unwrap().len(), 2);
+        assert_eq!(tst.find_similar(String::from("abc"), 0).len(), 0);
+
+        let copy = tst.clone();
+        drop(tst);
+        assert_eq!(copy.get(long.clone() + "c"), Some(2));
+        assert_eq!(copy.get_weight(long.clone() + "c"), Some(5));
+        assert_eq!(copy.get_words().len(), 28);
+        assert!(format!("{copy:?}").len() > long.len());
+    }
+
+    #[test]
+    fn clone_and_debug() {
+        let mut tst = Tst::<i32>::new();
+        tst.insert("bola", 1).unwrap();
+        tst.insert("bala", 2).unwrap();
+        tst.insert("hora", 3).unwrap();
+        assert!(tst.set_weight(String::from("hora"), 7));
+
+        let mut copy = tst.clone();
+        copy.insert("bola", 4).unwrap();
+        assert_eq!(tst.get(String::from("bola")), Some(1));
+        assert_eq!(copy.get(String::from("bola")), Some(4));
+        assert_eq!(
+            copy.find_top(String::new(), 1),
+            vec![("hora".to_string(), 3, 7)]
+        );
+        let mut words = copy.get_words();
+        words.sort_unstable();
+        assert_eq!(
+            words,
+            vec![
+                ("bala".to_string(), 2),
+                ("bola".to_string(), 4),
+                ("hora".to_string(), 3)
+            ]
+        );
+        assert_eq!(format!("{tst:?}"), r#"{"bola": 1, "bala": 2, "hora": 3}"#);
+    }
 }
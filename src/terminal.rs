@@ -1,6 +1,11 @@
 use std::borrow::Cow;
+use std::sync::Arc;
 
-use reedline::{Prompt, PromptEditMode, PromptHistorySearch, Reedline, Signal};
+use reedline::{
+    default_emacs_keybindings, ColumnarMenu, Completer, Emacs, KeyCode, KeyModifiers, Prompt,
+    PromptEditMode, PromptHistorySearch, Reedline, ReedlineEvent, ReedlineMenu, Signal, Span,
+    Suggestion,
+};
 use tabled::object::Segment;
 use tabled::{Modify, Style, Table, Tabled, Width};
 
@@ -8,15 +13,17 @@ use crate::knowledge::{QueryResult, DB};
 use crate::models::{JogadorComRating, User};
 use crate::parser::Query;
 use crate::reading::initialize;
+use crate::structures::hash_table::TableStats;
 
 pub fn main_loop() {
     println!("Inicializando...");
     let start = std::time::Instant::now();
     let mut db = DB::new();
     initialize(&mut db).unwrap();
+    let db = Arc::new(db);
     let elapsed = start.elapsed();
     println!("Inicializado em {:?}", elapsed);
-    let mut line_editor = Reedline::create();
+    let mut line_editor = create_line_editor(&db);
     let prompt = CleanPrompt::default();
     loop {
         let line = line_editor.read_line(&prompt);
@@ -59,6 +66,54 @@ pub fn main_loop() {
     }
 }
 
+const MENU_SUGESTOES: &str = "sugestoes";
+
+// Editor of the main loop: Tab lists the players with most ratings whose
+// name starts with what was typed after `player` or `suggest`.
+fn create_line_editor(db: &Arc<DB>) -> Reedline {
+    let mut keybindings = default_emacs_keybindings();
+    keybindings.add_binding(
+        KeyModifiers::NONE,
+        KeyCode::Tab,
+        ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu(MENU_SUGESTOES.to_string()),
+            ReedlineEvent::MenuNext,
+        ]),
+    );
+    let menu = ColumnarMenu::default().with_name(MENU_SUGESTOES);
+
+    Reedline::create()
+        .with_completer(Box::new(NomeCompleter { db: Arc::clone(db) }))
+        .with_menu(ReedlineMenu::EngineCompleter(Box::new(menu)))
+        .with_edit_mode(Box::new(Emacs::new(keybindings)))
+}
+
+struct NomeCompleter {
+    db: Arc<DB>,
+}
+
+impl Completer for NomeCompleter {
+    fn complete(&mut self, line: &str, pos: usize) -> Vec<Suggestion> {
+        let Some((comando, prefixo)) = line.get(..pos).and_then(|a| a.split_once(' ')) else {
+            return vec![];
+        };
+        if !matches!(comando, "player" | "suggest") || prefixo.trim().is_empty() {
+            return vec![];
+        }
+        self.db
+            .suggest(prefixo)
+            .into_iter()
+            .map(|jogador| Suggestion {
+                value: jogador.get_name().clone(),
+                description: Some(format!("{} avaliações", jogador.get_rating_count())),
+                extra: None,
+                span: Span::new(comando.len() + 1, pos),
+                append_whitespace: false,
+            })
+            .collect()
+    }
+}
+
 fn print_res(res: QueryResult, db: &DB) {
     match res {
         QueryResult::Jogadores(jogadores) => {
@@ -71,10 +126,129 @@ fn print_res(res: QueryResult, db: &DB) {
         QueryResult::User(user) => {
             show_user(user, db);
         }
+        QueryResult::Rank {
+            position,
+            total,
+            jogadores,
+        } => {
+            show_rank(&position, total, jogadores);
+        }
+        QueryResult::Stats(tabelas) => {
+            show_stats(&tabelas);
+        }
+        QueryResult::Similar(jogadores) => {
+            show_similar(jogadores);
+        }
+    }
+}
+
+#[derive(Debug, Tabled)]
+struct Estatisticas {
+    tabela: &'static str,
+    itens: usize,
+    baldes: usize,
+    carga: String,
+    vazios: String,
+    maior_cadeia: usize,
+    cadeia_media: String,
+}
+
+fn show_stats(tabelas: &[(&'static str, TableStats)]) {
+    let estatisticas = tabelas
+        .iter()
+        .map(|(tabela, stats)| Estatisticas {
+            tabela,
+            itens: stats.len,
+            baldes: stats.buckets,
+            carga: format!("{:.3}", stats.load_factor),
+            vazios: format!("{:.1}%", stats.empty_ratio * 100.0),
+            maior_cadeia: stats.max_chain,
+            cadeia_media: format!("{:.3}", stats.mean_chain),
+        })
+        .collect::<Vec<Estatisticas>>();
+
+    let mut table = Table::new(estatisticas);
+    table.with(Style::modern());
+    println!("{table}");
+
+    println!("Baldes por tamanho de cadeia:");
+    for (tabela, stats) in tabelas {
+        let histograma = stats
+            .histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, baldes)| **baldes > 0)
+            .map(|(tamanho, baldes)| format!("{tamanho}: {baldes}"))
+            .collect::<Vec<String>>()
+            .join(", ");
+        println!("\t{tabela}: {histograma}");
     }
 }
 
-fn show_jogadores(jogadores: &Vec<JogadorComRating>) {
+#[derive(Debug, Tabled)]
+struct Classificacao {
+    posicao: usize,
+    id: u32,
+    jogador: String,
+    nota: f32,
+    avaliacoes: u32,
+}
+
+fn show_rank(position: &str, total: usize, jogadores: Vec<(usize, &JogadorComRating)>) {
+    if jogadores.is_empty() {
+        println!("Nenhum jogador encontrado na posição {position}");
+        return;
+    }
+    println!("Posição no ranking de {position} ({total} jogadores classificados)");
+    let classificacoes = jogadores
+        .into_iter()
+        .map(|(posicao, jogador)| Classificacao {
+            posicao,
+            id: jogador.get_sofifa_id(),
+            jogador: jogador.get_name().clone(),
+            nota: jogador.get_rating(),
+            avaliacoes: jogador.get_rating_count(),
+        })
+        .collect::<Vec<Classificacao>>();
+
+    let mut table = Table::new(classificacoes);
+    table.with(Style::modern());
+    table.with(Modify::new(Segment::all()).with(Width::wrap(19)));
+    println!("{table}");
+}
+
+#[derive(Debug, Tabled)]
+struct Semelhante {
+    distancia: usize,
+    id: u32,
+    jogador: String,
+    nota: f32,
+    avaliacoes: u32,
+}
+
+fn show_similar(jogadores: Vec<(usize, &JogadorComRating)>) {
+    if jogadores.is_empty() {
+        println!("Nenhum jogador encontrado");
+        return;
+    }
+    let semelhantes = jogadores
+        .into_iter()
+        .map(|(distancia, jogador)| Semelhante {
+            distancia,
+            id: jogador.get_sofifa_id(),
+            jogador: jogador.get_name().clone(),
+            nota: jogador.get_rating(),
+            avaliacoes: jogador.get_rating_count(),
+        })
+        .collect::<Vec<Semelhante>>();
+
+    let mut table = Table::new(semelhantes);
+    table.with(Style::modern());
+    table.with(Modify::new(Segment::all()).with(Width::wrap(19)));
+    println!("{table}");
+}
+
+fn show_jogadores(jogadores: &[&JogadorComRating]) {
     if jogadores.is_empty() {
         println!("Nenhum jogador encontrado");
         return;
@@ -134,7 +308,7 @@ fn show_jogadores(jogadores: &Vec<JogadorComRating>) {
     }
 }
 
-fn show_jogador(jogador: JogadorComRating) {
+fn show_jogador(jogador: &JogadorComRating) {
     println!("Nome: {}", jogador.get_name());
     println!("Id: {}", jogador.get_sofifa_id());
     println!("Posição: {}", jogador.get_pos());
@@ -180,7 +354,7 @@ impl Avaliacao {
     }
 }
 
-fn show_user(user: User, db: &DB) {
+fn show_user(user: &User, db: &DB) {
     println!("Avaliações do usuário: {}", user.get_id());
     let avaliacoes = user.get_ratings();
     println!("O usuário avaliou {} jogadores", avaliacoes.len());